@@ -0,0 +1,85 @@
+//! Decoding benchmarks. Run with `cargo bench`.
+
+extern crate nbt;
+
+use nbt::decode::Decoder;
+
+use std::io::Cursor;
+use std::time::Instant;
+
+// Time `f` over `iters` runs and print the average.
+fn bench<F: FnMut()>(name: &str, iters: u32, mut f: F) {
+    f();
+
+    let start = Instant::now();
+
+    for _ in 0 .. iters {
+        f();
+    }
+
+    let per_iter = start.elapsed() / iters;
+    println!("{:<24} {:>10?}/iter", name, per_iter);
+}
+
+fn push_name(buf: &mut Vec<u8>, name: &str) {
+    buf.extend_from_slice(&[0, name.len() as u8]);
+    buf.extend_from_slice(name.as_bytes());
+}
+
+// A root compound holding `n` block positions, `{x: int, y: int, z: int}`.
+fn positions(n: usize) -> Vec<u8> {
+    let mut buf = vec![10];
+    push_name(&mut buf, "");
+
+    buf.extend_from_slice(&[9]);
+    push_name(&mut buf, "Positions");
+    buf.extend_from_slice(&[10, (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+
+    for i in 0 .. n {
+        for k in &["x", "y", "z"] {
+            buf.push(3);
+            push_name(&mut buf, k);
+            buf.extend_from_slice(&[0, 0, (i >> 8) as u8, i as u8]);
+        }
+
+        buf.push(0);
+    }
+
+    buf.push(0);
+    buf
+}
+
+// A root compound holding an int array and a long array of `n` elements.
+fn arrays(n: usize) -> Vec<u8> {
+    let mut buf = vec![10];
+    push_name(&mut buf, "");
+
+    for &(t, name, width) in &[(11, "Ints", 4), (12, "Longs", 8)] {
+        buf.push(t);
+        push_name(&mut buf, name);
+        buf.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+
+        for i in 0 .. n * width {
+            buf.push(i as u8);
+        }
+    }
+
+    buf.push(0);
+    buf
+}
+
+fn main() {
+    let data = positions(10000);
+
+    bench("positions (10000)", 100, || {
+        let mut dec = Decoder::from_reader(Cursor::new(data.clone()));
+        dec.read_tag().unwrap();
+    });
+
+    let data = arrays(100000);
+
+    bench("arrays (100000)", 100, || {
+        let mut dec = Decoder::from_reader(Cursor::new(data.clone()));
+        dec.read_tag().unwrap();
+    });
+}
@@ -0,0 +1,179 @@
+//! Derive macros for `nbt::traits::ToNbt` and `nbt::traits::FromNbt`,
+//! re-exported by `nbt` with its `derive` feature.
+//!
+//! Structs with named fields map to compounds, with one entry per field
+//! keyed by the field's name. Fields accept these attributes:
+//!
+//! * `#[nbt(rename = "Health")]` uses a different key for the field.
+//! * `#[nbt(skip)]` leaves the field out; it's filled with its `Default`
+//!   value when converting from NBT.
+//! * `#[nbt(ignore_eq)]` still converts the field, but leaves it out of the
+//!   comparison done by `nbt_eq`.
+//!
+//! Deriving `ToNbt` also generates an `nbt_eq` method, which compares the
+//! converted fields with `==` if they implement `PartialEq`, and by their
+//! NBT otherwise. Fields marked `skip` or `ignore_eq` aren't compared.
+//!
+//! Fields are converted with their own `ToNbt` and `FromNbt` impls. Fields
+//! converting to `Tag::End`, like `None`, are left out of the compound, and
+//! missing entries are converted from `Tag::End`, so `Option` fields may be
+//! absent.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr};
+
+struct Field {
+    ident: syn::Ident,
+    key: String,
+    skip: bool,
+    ignore_eq: bool
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    let named = match input.data {
+        Data::Struct(ref s) => match s.fields {
+            Fields::Named(ref f) => &f.named,
+            _ => return Err(syn::Error::new_spanned(input, "expected a struct with named fields"))
+        },
+        _ => return Err(syn::Error::new_spanned(input, "expected a struct with named fields"))
+    };
+
+    let mut out = Vec::new();
+
+    for f in named {
+        let ident = f.ident.clone().unwrap();
+        let mut field = Field { key: ident.to_string(), ident, skip: false, ignore_eq: false };
+
+        for attr in f.attrs.iter().filter(|a| a.path().is_ident("nbt")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    field.key = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    field.skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("ignore_eq") {
+                    field.ignore_eq = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown nbt attribute"))
+                }
+            })?;
+        }
+
+        out.push(field);
+    }
+
+    Ok(out)
+}
+
+fn expand_to_nbt(input: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields: Vec<Field> = fields(input)?.into_iter().filter(|f| !f.skip).collect();
+
+    let inserts = fields.iter().map(|f| {
+        let (ident, key) = (&f.ident, &f.key);
+
+        quote! {
+            match ::nbt::traits::ToNbt::to_nbt(&self.#ident) {
+                ::nbt::Tag::End => (),
+                t => { c.elements.insert(#key.to_owned(), t); }
+            }
+        }
+    });
+
+    let compares = fields.iter().filter(|f| !f.ignore_eq).map(|f| {
+        let ident = &f.ident;
+
+        quote! {
+            && (&::nbt::traits::FieldEq(&self.#ident, &other.#ident)).field_eq()
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::nbt::traits::ToNbt for #name #ty_generics #where_clause {
+            fn to_nbt(&self) -> ::nbt::Tag {
+                let mut c = ::nbt::CompoundData::new();
+                #(#inserts)*
+                ::nbt::Tag::Compound(c)
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Compare `self` and `other` by the fields converted to NBT,
+            /// leaving out fields marked `#[nbt(skip)]` or
+            /// `#[nbt(ignore_eq)]`.
+            #[allow(dead_code)]
+            pub fn nbt_eq(&self, other: &Self) -> bool {
+                #[allow(unused_imports)]
+                use ::nbt::traits::{FieldEqDirect, FieldEqNbt};
+
+                true #(#compares)*
+            }
+        }
+    })
+}
+
+fn expand_from_nbt(input: &DeriveInput) -> syn::Result<Tokens> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let inits = fields(input)?.into_iter().map(|f| {
+        let (ident, key) = (f.ident, f.key);
+
+        if f.skip {
+            quote! { #ident: ::std::default::Default::default() }
+        } else {
+            quote! {
+                #ident: ::nbt::traits::FromNbt::from_nbt(
+                    c.elements.get(#key).unwrap_or(&::nbt::Tag::End))?
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::nbt::traits::FromNbt for #name #ty_generics #where_clause {
+            fn from_nbt(val: &::nbt::Tag) -> ::std::option::Option<Self> {
+                let c = match *val {
+                    ::nbt::Tag::Compound(ref c) => c,
+                    _ => return ::std::option::Option::None
+                };
+
+                ::std::option::Option::Some(#name {
+                    #(#inits),*
+                })
+            }
+        }
+    })
+}
+
+fn derive(input: TokenStream, expand: fn(&DeriveInput) -> syn::Result<Tokens>) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(x)  => x.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+/// Derive `ToNbt`, converting the struct to a compound, along with an
+/// `nbt_eq` method comparing the converted fields.
+#[proc_macro_derive(ToNbt, attributes(nbt))]
+pub fn derive_to_nbt(input: TokenStream) -> TokenStream {
+    derive(input, expand_to_nbt)
+}
+
+/// Derive `FromNbt`, converting the struct from a compound.
+#[proc_macro_derive(FromNbt, attributes(nbt))]
+pub fn derive_from_nbt(input: TokenStream) -> TokenStream {
+    derive(input, expand_from_nbt)
+}
@@ -1,174 +1,1819 @@
 //! Decode NBT values from files or other readable sources.
-use std;
 
-use super::{Error, Result, Tag, TagType, ListData, CompoundData, Decodable, Compression};
+use super::{Error, Result, Tag, TagType, ListData, CompoundData, Map, Decodable, Compression, Endianness};
+use path::{self, Segment};
+use mutf8;
+use util::{StringInterner, ContentHasher, AutoDecompressReader, sniff_compression};
 
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
+#[cfg(test)]
+use std::io::Cursor;
 use std::collections::HashMap;
+use std::hash::Hasher;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Instant;
 
-use flate2::read::GzDecoder;
+use flate2::read::{GzDecoder, ZlibDecoder};
 
 
-fn read_string<R: Read>(reader: &mut R) -> Result<String> {
-    let mut raw_name_len = [0_u8; 2];
-    reader.read(&mut raw_name_len)?;
+// Decode the first tag in `data`, with the compression guessed by
+// `sniff_compression`. A Zlib header is only two bytes, which can also start
+// an uncompressed string tag, so only that guess falls back to reading the
+// data uncompressed. The error from the guess is the one returned.
+fn read_auto(data: &[u8]) -> Result<((String, Tag), Compression)> {
+    let c = sniff_compression(data);
 
-    let name_len = i16::from_bytes_nbt(&raw_name_len).unwrap() as usize;
+    let res = match c {
+        Compression::GZip => Decoder::from_reader(GzDecoder::new(data)).read_tag(),
+        Compression::Zlib => Decoder::from_reader(ZlibDecoder::new(data)).read_tag(),
+        _                 => Decoder::from_reader(data).read_tag()
+    };
 
-    Ok(if name_len > 0 {
-        let mut raw_name_dat = vec![0; name_len].into_boxed_slice();
-        reader.read(&mut *raw_name_dat)?;
+    match res {
+        Ok(t) => Ok((t, c)),
 
-        String::from_utf8_lossy(&*raw_name_dat).into_owned()
-    } else {
-        "".to_owned()
-    })
+        Err(e) => if c == Compression::Zlib {
+            Decoder::from_reader(data).read_tag()
+                .map(|t| (t, Compression::Uncompressed))
+                .map_err(|_| e)
+        } else {
+            Err(e)
+        }
+    }
+}
+
+/// Decode the first named tag in `data`.
+///
+/// The root name is returned as it was read. Java Edition leaves it empty,
+/// but some tools name the root compound, and `encode::to_bytes` writes it
+/// back unchanged.
+pub fn from_bytes(data: &[u8]) -> Result<(String, Tag)> {
+    Decoder::from_reader(data).read_tag()
+}
+
+/// Decode the first named tag in `data`, returning it along with the bytes
+/// that follow it.
+pub fn decode_prefix(data: &[u8]) -> Result<((String, Tag), &[u8])> {
+    let mut rest = data;
+    let tag = Decoder::from_reader(&mut rest).read_tag()?;
+
+    Ok((tag, rest))
+}
+
+impl Tag {
+    /// Decode the first named tag in uncompressed `data`, like `from_bytes`.
+    pub fn from_bytes(data: &[u8]) -> Result<(String, Tag)> {
+        from_bytes(data)
+    }
+}
+
+/// Byte ranges of every subtree of a tag, as recorded by
+/// `Decoder::read_tag_indexed`.
+///
+/// Ranges cover a value's payload only, excluding the type ID and name of
+/// compound entries, and are counted from the first byte the decoder read.
+#[derive(Debug, PartialEq)]
+pub struct TagIndex {
+    ranges: HashMap<String, (u64, u64)>
+}
+
+impl TagIndex {
+    /// Return the `(start, end)` byte range of the value at `path`.
+    pub fn range(&self, path: &str) -> Option<(u64, u64)> {
+        self.ranges.get(path).cloned()
+    }
+
+    /// Return the number of recorded ranges.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Return whether no ranges were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+// Counts the bytes read from the underlying source, so progress on
+// compressed files can be reported against the size on disk.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+
+        Ok(n)
+    }
+}
+
+/// A legacy construct found by a decoder with legacy detection enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Legacy {
+    /// A UUID stored as a pair of longs, `UUIDMost` and `UUIDLeast` or the
+    /// same with a prefix like `Owner`, instead of an int array. Reported
+    /// at the `UUIDMost` entry.
+    UuidPair,
+    /// A numeric `id` short, as used for items and blocks before they had
+    /// namespaced string IDs.
+    NumericId,
+    /// A `Blocks` byte array of numeric block IDs, as used in chunk sections
+    /// before block palettes.
+    NumericBlocks
+}
+
+/// A legacy construct at a path, recorded while decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub path: String,
+    pub kind: Legacy
+}
+
+type ProgressFn = dyn FnMut(u64, Option<u64>);
+type StringDecoderFn = dyn Fn(&[u8]) -> Result<String>;
+
+/// Decode NBT tags.
+pub struct Decoder<R> {
+    reader: R,
+    pos: u64,
+    path: Vec<Segment>,
+    index: Option<TagIndex>,
+    max_compound_entries: Option<usize>,
+    max_initial_reserve: usize,
+    max_alloc: usize,
+    max_depth: usize,
+    deadline: Option<Instant>,
+    values_read: u64,
+    total_len: Option<u64>,
+    source_pos: Option<Rc<Cell<u64>>>,
+    progress: Option<Box<ProgressFn>>,
+    endianness: Endianness,
+    interner: Option<Rc<RefCell<StringInterner>>>,
+    hasher: Option<ContentHasher>,
+    frame_compression: bool,
+    string_decoder: Option<Rc<StringDecoderFn>>,
+    detect_legacy: bool,
+    warnings: Vec<Warning>
+}
+
+impl Decoder<Box<dyn Read>> {
+    /// Create a new Decoder from an existing reader, uncompressing its data
+    /// with the given compression method.
+    pub fn from_reader_compressed<S: Read + 'static>(reader: S, c: Compression) -> Decoder<Box<dyn Read>> {
+        let reader: Box<dyn Read> = match c {
+            Compression::Uncompressed =>
+                Box::new(reader),
+            Compression::GZip | Compression::GZipStored =>
+                Box::new(GzDecoder::new(reader)),
+            Compression::Zlib =>
+                Box::new(ZlibDecoder::new(reader)),
+            Compression::Auto =>
+                Box::new(AutoDecompressReader::new(reader))
+        };
+
+        Decoder::from_reader(reader)
+    }
+
+    /// Create a new Decoder for the given file `file`, with the given
+    /// compression method.
+    ///
+    /// The total length reported to the progress callback is the file's size
+    /// on disk. The uncompressed size of compressed files isn't known up
+    /// front, so for those the progress is the number of compressed bytes
+    /// read so far instead.
+    pub fn from_file(file: &str, c: Compression) -> Result<Decoder<Box<dyn Read>>> {
+        let f = File::open(file)?;
+        let len = f.metadata()?.len();
+        let count = Rc::new(Cell::new(0));
+
+        let mut decoder = Decoder::from_reader_compressed(CountingReader {
+            inner: f,
+            count: count.clone()
+        }, c);
+
+        decoder.source_pos = Some(count);
+        decoder.total_len = Some(len);
+
+        Ok(decoder)
+    }
+
+    /// Read the first named tag from `file`, detecting its compression.
+    ///
+    /// The compression is guessed from the file's magic bytes, as with
+    /// `util::sniff_compression`, and the data is decoded once under that
+    /// guess. A Zlib guess can't be told apart from an uncompressed string
+    /// tag, so if decoding it fails the data is read uncompressed instead.
+    /// The method that worked is returned alongside the tag.
+    pub fn read_file_auto(file: &str) -> Result<((String, Tag), Compression)> {
+        let mut data = Vec::new();
+        File::open(file)?.read_to_end(&mut data)?;
+
+        read_auto(&data)
+    }
 }
 
-fn read_primitive<R: Read, T: Decodable>(reader: &mut R) -> Result<T> {
-    let siz = unsafe { std::intrinsics::size_of::<T>() };
+impl<R: Read> Decoder<R> {
+    /// Create a new Decoder from an existing reader that will be taken
+    /// ownership over.
+    pub fn from_reader(reader: R) -> Decoder<R> {
+        Decoder {
+            reader,
+            pos: 0,
+            path: Vec::new(),
+            index: None,
+            max_compound_entries: None,
+            max_initial_reserve: 4096,
+            max_alloc: 64 << 20,
+            max_depth: 512,
+            deadline: None,
+            values_read: 0,
+            total_len: None,
+            source_pos: None,
+            progress: None,
+            endianness: Endianness::Big,
+            interner: None,
+            hasher: None,
+            frame_compression: false,
+            string_decoder: None,
+            detect_legacy: false,
+            warnings: Vec::new()
+        }
+    }
+
+    /// Create a new Decoder from an existing reader, reading numbers in the
+    /// given byte order.
+    pub fn from_reader_with(reader: R, e: Endianness) -> Decoder<R> {
+        let mut decoder = Decoder::from_reader(reader);
+        decoder.endianness = e;
+        decoder
+    }
+
+    /// Limit the number of entries a single compound may have, failing with
+    /// `Error::LimitExceeded` on compounds with more. Unlimited by default.
+    pub fn set_max_compound_entries(&mut self, max: Option<usize>) {
+        self.max_compound_entries = max;
+    }
+
+    /// Limit how many elements are reserved up front for lists and arrays.
+    ///
+    /// Larger lists and arrays are still decoded, but their storage grows as
+    /// elements arrive instead of trusting the declared length. Defaults to
+    /// 4096.
+    pub fn set_max_initial_reserve(&mut self, max: usize) {
+        self.max_initial_reserve = max;
+    }
+
+    /// Limit how deeply lists and compounds may be nested, failing with
+    /// `Error::LimitExceeded` on deeper ones instead of overflowing the
+    /// stack. The root counts as the first level. Defaults to 512.
+    pub fn set_max_depth(&mut self, max: usize) {
+        self.max_depth = max;
+    }
+
+    /// Give up decoding with `Error::TimedOut` once `deadline` has passed,
+    /// or never if `None` (the default).
+    ///
+    /// The clock is only checked every 1024 values, so decoding may run
+    /// slightly past the deadline before it's noticed.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Limit the declared size of a single array or list, failing with
+    /// `Error::LimitExceeded` on larger ones. Defaults to 64 MiB.
+    ///
+    /// The size is the element count times the width of the elements, or
+    /// times one for lists of strings, lists and compounds.
+    pub fn set_max_alloc(&mut self, max: usize) {
+        self.max_alloc = max;
+    }
+
+    /// Set the total length of the stream in bytes, which is passed on to
+    /// the progress callback.
+    pub fn set_total_len(&mut self, len: u64) {
+        self.total_len = Some(len);
+    }
+
+    /// Call `f` with the number of bytes read so far and the total length,
+    /// if known, whenever data is read from the stream.
+    pub fn set_progress<F: FnMut(u64, Option<u64>) + 'static>(&mut self, f: F) {
+        self.progress = Some(Box::new(f));
+    }
+
+    /// Add every string decoded from now on, both names and values, to
+    /// `interner`.
+    ///
+    /// This only collects the unique strings of the data read, e.g. to build
+    /// a palette across many files, and saves no memory: decoded tags still
+    /// own their strings, and every string not yet in the pool is allocated
+    /// a second time for it.
+    pub fn set_interner(&mut self, interner: Rc<RefCell<StringInterner>>) {
+        self.interner = Some(interner);
+    }
+
+    /// Decode the bytes of strings, both names and values, with `f` instead
+    /// of as modified UTF-8.
+    ///
+    /// This is for data written with another text encoding. Empty strings
+    /// are always decoded as such, without calling `f`.
+    pub fn set_string_decoder<F: Fn(&[u8]) -> Result<String> + 'static>(&mut self, f: F) {
+        self.string_decoder = Some(Rc::new(f));
+    }
+
+    /// Record a warning for every legacy construct in the compounds decoded
+    /// from now on, as described by `Legacy`. Defaults to false.
+    pub fn set_detect_legacy(&mut self, detect: bool) {
+        self.detect_legacy = detect;
+    }
+
+    /// Return the warnings recorded so far, in decoding order.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Expect the frames read by `read_network_frame` to be in the format
+    /// used once a connection has enabled compression. Defaults to false.
+    pub fn set_frame_compression(&mut self, enabled: bool) {
+        self.frame_compression = enabled;
+    }
+
+    /// Consume the bytes `magic` from the stream, failing with
+    /// `Error::Malformed` if the stream doesn't start with them.
+    ///
+    /// This is for formats that frame NBT data with a leading magic number;
+    /// call it before reading the tag.
+    pub fn skip_magic(&mut self, magic: &[u8]) -> Result<()> {
+        let mut buf = vec![0_u8; magic.len()];
+
+        self.read_bytes(&mut buf)?;
+
+        if &buf[..] != magic {
+            return Err(Error::Malformed);
+        }
+
+        Ok(())
+    }
+
+    /// Read a named tag from the stream.
+    pub fn read_tag(&mut self) -> Result<(String, Tag)> {
+        self.read_named()
+    }
+
+    /// Read a tag without a name from the stream, as sent over the network
+    /// since Minecraft 1.20.2: a type byte followed directly by the payload.
+    pub fn read_tag_unnamed(&mut self) -> Result<Tag> {
+        let t = self.read_type()?;
+
+        if t == TagType::End {
+            return Ok(Tag::End);
+        }
+
+        self.read_value(t)
+    }
+
+    /// Read a named tag from the stream, recording the byte range each of
+    /// its subtrees occupies.
+    pub fn read_tag_indexed(&mut self) -> Result<(String, Tag, TagIndex)> {
+        self.index = Some(TagIndex { ranges: HashMap::new() });
+
+        let res = self.read_named();
+        let index = self.index.take().unwrap();
+
+        res.map(|(n, v)| (n, v, index))
+    }
+
+    /// Read a named tag from the stream, hashing its payload as it's read.
+    ///
+    /// The hash is folded over the payload in stream order, so it matches
+    /// `Tag::content_hash` of the result when compound entries iterate in
+    /// the order they were written in, e.g. with the `btreemap` feature and
+    /// sorted input.
+    pub fn read_tag_hashed(&mut self) -> Result<((String, Tag), u64)> {
+        let t = self.read_type()?;
+
+        if t == TagType::End {
+            return Ok((("".to_owned(), Tag::End), Tag::End.content_hash()));
+        }
+
+        let name = self.read_string()?;
+
+        self.hasher = Some(ContentHasher::new());
+        let res = self.read_value(t);
+        let hash = self.hasher.take().unwrap().finish();
+
+        Ok(((name, res?), hash))
+    }
+
+    /// Advance past a value of type `t` without building it.
+    ///
+    /// Fixed width values and arrays are skipped by their size, lists and
+    /// compounds are walked but nothing in them is kept.
+    pub fn skip_value(&mut self, t: TagType) -> Result<()> {
+        self.skip_payload(t, 0)
+    }
+
+    /// Advance past a named tag without building it.
+    pub fn skip_tag(&mut self) -> Result<()> {
+        let t = self.read_type()?;
+
+        if t == TagType::End {
+            return Ok(());
+        }
+
+        self.skip_payload(TagType::String, 0)?;
+        self.skip_payload(t, 0)
+    }
+
+    /// Read a named tag from a length-prefixed network frame, as used by
+    /// the Minecraft Java protocol.
+    ///
+    /// The frame starts with its length as a VarInt. With frame compression
+    /// enabled, that's followed by the uncompressed length as another VarInt,
+    /// where zero means the rest of the frame isn't compressed, and otherwise
+    /// the rest is zlib-compressed. A compressed frame that doesn't inflate
+    /// to exactly the declared length is rejected as malformed. Any bytes in
+    /// the frame after the tag are ignored, and the decoder is left at the
+    /// start of the next frame.
+    pub fn read_network_frame(&mut self) -> Result<(String, Tag)> {
+        let len = self.read_var_int()?;
+
+        if len > self.max_alloc {
+            return Err(Error::LimitExceeded(path::format(&self.path)));
+        }
+
+        let frame = self.read_byte_vec(len)?;
+        let mut data = &frame[..];
+
+        let uncompressed = if self.frame_compression {
+            Decoder::from_reader(&mut data).read_var_int()?
+        } else {
+            0
+        };
+
+        if uncompressed > self.max_alloc {
+            return Err(Error::LimitExceeded(path::format(&self.path)));
+        }
+
+        if uncompressed == 0 {
+            return self.read_nested(data);
+        }
+
+        // Read one byte past the declared length, so a stream that inflates
+        // to more than that is caught without inflating all of it
+        let mut raw = Vec::with_capacity(uncompressed);
+        ZlibDecoder::new(data).take(uncompressed as u64 + 1).read_to_end(&mut raw)?;
+
+        if raw.len() != uncompressed {
+            return Err(Error::Malformed);
+        }
+
+        self.read_nested(&raw[..])
+    }
+
+    // Read a named tag from data embedded in this decoder's stream, with the
+    // same byte order, limits and callbacks. Progress is reported at this
+    // decoder's position, since the embedded data doesn't map onto it.
+    fn read_nested<S: Read>(&mut self, reader: S) -> Result<(String, Tag)> {
+        let mut decoder = Decoder::from_reader_with(reader, self.endianness);
+
+        decoder.max_compound_entries = self.max_compound_entries;
+        decoder.max_initial_reserve = self.max_initial_reserve;
+        decoder.max_alloc = self.max_alloc;
+        decoder.max_depth = self.max_depth;
+        decoder.deadline = self.deadline;
+        decoder.interner = self.interner.clone();
+        decoder.string_decoder = self.string_decoder.clone();
+        decoder.total_len = self.total_len;
+        decoder.source_pos = self.source_pos.clone()
+            .or_else(|| Some(Rc::new(Cell::new(self.pos))));
+        decoder.progress = self.progress.take();
+        decoder.hasher = self.hasher;
+
+        let res = decoder.read_tag();
+
+        self.progress = decoder.progress;
+        self.hasher = decoder.hasher;
+
+        res
+    }
+
+    // Read a protocol VarInt: seven bits per byte, least significant group
+    // first, with the high bit set on all but the last byte.
+    fn read_var_int(&mut self) -> Result<usize> {
+        let mut value = 0_u32;
+
+        for i in 0 .. 5 {
+            let mut b = [0_u8; 1];
+            self.read_bytes(&mut b)?;
+
+            value |= ((b[0] & 0x7F) as u32) << (7 * i);
+
+            if b[0] & 0x80 == 0 {
+                // Lengths can't be negative
+                if (value as i32) < 0 {
+                    return Err(Error::Malformed);
+                }
+
+                return Ok(value as usize);
+            }
+        }
+
+        Err(Error::Malformed)
+    }
+
+    fn reserve(&self, len: usize) -> usize {
+        len.min(self.max_initial_reserve)
+    }
+
+    // Read the length of an array or list with elements `width` bytes wide,
+    // rejecting negative and overly large lengths.
+    fn read_len(&mut self, width: usize) -> Result<usize> {
+        let len = self.read_primitive::<i32>()?;
+
+        if len < 0 {
+            return Err(Error::Malformed);
+        }
+
+        if (len as usize).saturating_mul(width) > self.max_alloc {
+            return Err(Error::LimitExceeded(path::format(&self.path)));
+        }
+
+        Ok(len as usize)
+    }
+
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => {
+                self.pos += buf.len() as u64;
+
+                if let Some(ref mut f) = self.progress {
+                    let read = self.source_pos.as_ref().map_or(self.pos, |c| c.get());
+                    f(read, self.total_len);
+                }
+
+                Ok(())
+            },
+
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof =>
+                Err(Error::UnexpectedEof),
+
+            Err(e) => Err(Error::from(e))
+        }
+    }
+
+    // Fold bytes into the content hash, in big endian order for numbers.
+    fn hash(&mut self, bytes: &[u8]) {
+        if let Some(ref mut h) = self.hasher {
+            h.write(bytes);
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String> {
+        // The length is unsigned in the modified UTF-8 encoding Java uses
+        let name_len = self.read_primitive::<i16>()? as u16 as usize;
+
+        let s = if name_len > 0 {
+            let mut raw_name_dat = vec![0; name_len].into_boxed_slice();
+            self.read_bytes(&mut raw_name_dat)?;
+
+            self.hash(&raw_name_dat);
+
+            match self.string_decoder {
+                Some(ref f) => f(&raw_name_dat)?,
+                None        => mutf8::decode(&raw_name_dat).ok_or(Error::Malformed)?
+            }
+        } else {
+            "".to_owned()
+        };
+
+        if let Some(ref interner) = self.interner {
+            interner.borrow_mut().intern(&s);
+        }
+
+        Ok(s)
+    }
+
+    fn read_primitive<T: Decodable>(&mut self) -> Result<T> {
+        // No primitive is wider than a long or double, so a scratch buffer
+        // on the stack holds any of them without allocating
+        let mut buf = [0_u8; 8];
+        let slice = &mut buf[.. T::SIZE];
+        self.read_bytes(slice)?;
+
+        if self.endianness == Endianness::Little {
+            slice.reverse();
+        }
+
+        self.hash(slice);
+
+        match T::from_bytes_nbt(slice) {
+            Some(x) => Ok(x),
+            None    => Err(Error::Malformed),
+        }
+    }
+
+    fn read_value(&mut self, vtype: TagType) -> Result<Tag> {
+        let start = self.pos;
+        let v = self.read_payload(vtype)?;
+
+        if self.index.is_some() {
+            let p = path::format(&self.path);
+            let end = self.pos;
+
+            if let Some(ref mut index) = self.index {
+                index.ranges.insert(p, (start, end));
+            }
+        }
+
+        Ok(v)
+    }
+
+    // Anything bigger than a primitive is read in its own function, to keep
+    // the frames of the recursion through here small.
+    fn read_payload(&mut self, vtype: TagType) -> Result<Tag> {
+        if (vtype == TagType::List || vtype == TagType::Compound) && self.path.len() >= self.max_depth {
+            return Err(Error::LimitExceeded(path::format(&self.path)));
+        }
+
+        self.values_read += 1;
+
+        if self.values_read.is_multiple_of(1024) && self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(Error::TimedOut);
+        }
+
+        match vtype {
+            // Can't read the end marker as an actual tag
+            TagType::End   => Err(Error::Malformed),
+
+            TagType::Byte   => Ok(Tag::Byte(self.read_primitive()?)),
+            TagType::Short  => Ok(Tag::Short(self.read_primitive()?)),
+            TagType::Int    => Ok(Tag::Int(self.read_primitive()?)),
+            TagType::Long   => Ok(Tag::Long(self.read_primitive()?)),
+            TagType::Float  => Ok(Tag::Float(self.read_primitive()?)),
+            TagType::Double => Ok(Tag::Double(self.read_primitive()?)),
+
+            TagType::ByteArray => self.read_byte_array(),
+
+            TagType::String => Ok(Tag::String(self.read_string()?)),
+
+            TagType::List     => self.read_list(),
+            TagType::Compound => self.read_compound(),
+
+            TagType::IntArray  => self.read_int_array(),
+            TagType::LongArray => self.read_long_array()
+        }
+    }
+
+    fn read_byte_array(&mut self) -> Result<Tag> {
+        let len = self.read_len(1)?;
+        let bytes = self.read_byte_vec(len)?;
+
+        self.hash(&bytes);
+
+        Ok(Tag::ByteArray(bytes))
+    }
+
+    fn read_byte_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.reserve(len));
+
+        // Read in bounded steps so the buffer only grows as far as the data
+        // actually goes
+        while bytes.len() < len {
+            let start = bytes.len();
+            let step = self.reserve(len - start).max(1);
+
+            bytes.resize(start + step, 0);
+            self.read_bytes(&mut bytes[start ..])?;
+        }
+
+        Ok(bytes)
+    }
+
+    // Read the elements of an int or long array in bulk, a bounded number
+    // of them per read.
+    fn read_array<T: Decodable>(&mut self) -> Result<Vec<T>> {
+        let len = self.read_len(T::SIZE)?;
+        let mut values = Vec::with_capacity(self.reserve(len));
+        let mut buf = Vec::new();
+
+        while values.len() < len {
+            let step = self.reserve(len - values.len()).max(1);
+
+            buf.resize(step * T::SIZE, 0);
+            self.read_bytes(&mut buf)?;
+
+            if self.endianness == Endianness::Little {
+                for e in buf.chunks_exact_mut(T::SIZE) {
+                    e.reverse();
+                }
+            }
+
+            self.hash(&buf);
+            values.extend(buf.chunks_exact(T::SIZE).map(|e| T::from_bytes_nbt(e).unwrap()));
+        }
+
+        Ok(values)
+    }
+
+    fn read_int_array(&mut self) -> Result<Tag> {
+        Ok(Tag::IntArray(self.read_array()?))
+    }
+
+    fn read_long_array(&mut self) -> Result<Tag> {
+        Ok(Tag::LongArray(self.read_array()?))
+    }
+
+    fn read_list(&mut self) -> Result<Tag> {
+        let et = self.read_primitive::<i8>()?;
+        let tt = TagType::from_binary(et as u8);
+
+        if tt.is_none() && et != 0 {
+            return Err(Error::Malformed);
+
+        }
+
+        let width = tt.and_then(|t| t.fixed_size()).unwrap_or(1);
+        let len = self.read_len(width)?;
+        let mut vec = Vec::with_capacity(self.reserve(len));
+
+        for i in 0 .. len {
+            self.path.push(Segment::Index(i));
+            let v = self.read_value(tt.unwrap());
+            self.path.pop();
+
+            vec.push(v?);
+        }
+
+        Ok(Tag::List(ListData {
+            element_type: TagType::from_binary(et as u8).unwrap(),
+            elements: vec
+        }))
+    }
+
+    fn read_compound(&mut self) -> Result<Tag> {
+        let mut map = Map::new();
+
+        loop {
+            let t = self.read_type()?;
+
+            if t == TagType::End {
+                break;
+            }
+
+            let name = self.read_string()?;
+
+            // Fixed-width primitives have no children, so unless
+            // their range is needed they can skip the path tracking.
+            // This is the common case for small compounds like
+            // block positions.
+            if t.fixed_size().is_some() && self.index.is_none() {
+                let v = self.read_payload(t)?;
+                map.insert(name, v);
+            } else {
+                self.path.push(Segment::Key(name));
+                let v = self.read_value(t);
+                let name = match self.path.pop() {
+                    Some(Segment::Key(name)) => name,
+                    _                        => unreachable!()
+                };
+
+                map.insert(name, v?);
+            }
+
+            if self.max_compound_entries.is_some_and(|m| map.len() > m) {
+                return Err(Error::LimitExceeded(path::format(&self.path)));
+            }
+        }
+
+        if self.detect_legacy {
+            self.check_legacy(&map);
+        }
+
+        Ok(Tag::Compound(CompoundData { elements: map }))
+    }
+
+    // Record a warning for each legacy construct among the entries of a
+    // compound.
+    fn check_legacy(&mut self, map: &Map) {
+        let base = path::format(&self.path);
+        let mut found = Vec::new();
+
+        for (k, v) in map.iter() {
+            let kind = match (&k[..], v) {
+                ("id", &Tag::Short(_))         => Some(Legacy::NumericId),
+                ("Blocks", &Tag::ByteArray(_)) => Some(Legacy::NumericBlocks),
+
+                (_, &Tag::Long(_)) if k.ends_with("UUIDMost") => {
+                    let least = format!("{}UUIDLeast", &k[.. k.len() - 8]);
+
+                    match map.get(&least[..]) {
+                        Some(&Tag::Long(_)) => Some(Legacy::UuidPair),
+                        _                   => None
+                    }
+                },
+
+                _ => None
+            };
+
+            if let Some(kind) = kind {
+                found.push(Warning { path: path::join_key(&base, k), kind });
+            }
+        }
+
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        self.warnings.extend(found);
+    }
+
+    fn read_type(&mut self) -> Result<TagType> {
+        let mut header = [0_u8; 1];
+        self.read_bytes(&mut header)?;
+        self.hash(&header);
+
+        TagType::from_binary(header[0]).ok_or(Error::Malformed)
+    }
+
+    fn read_named(&mut self) -> Result<(String, Tag)> {
+        let t = self.read_type()?;
+
+        if t == TagType::End {
+            return Ok(("".to_owned(), Tag::End));
+        }
+
+        let name = self.read_string()?;
+        let v = self.read_value(t)?;
+
+        Ok((name, v))
+    }
+
+    fn skip_bytes(&mut self, mut n: u64) -> Result<()> {
+        let mut buf = [0_u8; 512];
+
+        while n > 0 {
+            let step = n.min(buf.len() as u64) as usize;
+            self.read_bytes(&mut buf[.. step])?;
+            n -= step as u64;
+        }
+
+        Ok(())
+    }
+
+    // Read an array or list length for skipping, which needs no allocation
+    // limit.
+    fn read_skip_len(&mut self) -> Result<u64> {
+        let len = self.read_primitive::<i32>()?;
+
+        if len < 0 {
+            return Err(Error::Malformed);
+        }
+
+        Ok(len as u64)
+    }
+
+    // Advance past a value without building it. `depth` counts the levels
+    // entered below the current path.
+    fn skip_payload(&mut self, vtype: TagType, depth: usize) -> Result<()> {
+        if let Some(n) = vtype.fixed_size() {
+            return self.skip_bytes(n as u64);
+        }
+
+        if (vtype == TagType::List || vtype == TagType::Compound) && self.path.len() + depth >= self.max_depth {
+            return Err(Error::LimitExceeded(path::format(&self.path)));
+        }
+
+        match vtype {
+            TagType::String => {
+                let len = self.read_primitive::<i16>()? as u16;
+                self.skip_bytes(len as u64)
+            },
+
+            TagType::ByteArray => { let len = self.read_skip_len()?; self.skip_bytes(len) },
+            TagType::IntArray  => { let len = self.read_skip_len()?; self.skip_bytes(len * 4) },
+            TagType::LongArray => { let len = self.read_skip_len()?; self.skip_bytes(len * 8) },
+
+            TagType::List => {
+                let t = self.read_type()?;
+                let len = self.read_skip_len()?;
+
+                match t.fixed_size() {
+                    Some(n) => self.skip_bytes(len * n as u64),
+                    None    => {
+                        for _ in 0 .. len {
+                            self.skip_payload(t, depth + 1)?;
+                        }
+
+                        Ok(())
+                    }
+                }
+            },
 
-    let mut slice = vec![0; siz].into_boxed_slice();
-    reader.read(&mut *slice)?;
+            TagType::Compound => self.skip_entries(depth + 1),
 
-    match T::from_bytes_nbt(&slice) {
-        Some(x) => Ok(x),
-        None    => Err(Error::Malformed),
+            _ => Err(Error::Malformed)
+        }
+    }
+
+    // Advance past the remaining entries of a compound and its end marker.
+    fn skip_entries(&mut self, depth: usize) -> Result<()> {
+        loop {
+            let t = self.read_type()?;
+
+            if t == TagType::End {
+                return Ok(());
+            }
+
+            self.skip_payload(TagType::String, depth)?;
+            self.skip_payload(t, depth)?;
+        }
     }
 }
 
+/// An event emitted by `EventDecoder`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The name and type of the root tag or of a compound entry. Its value
+    /// follows.
+    Named(String, TagType),
+    /// The start of a compound. Its entries follow, each introduced by a
+    /// `Named` event, until the matching `End`.
+    StartCompound,
+    /// The start of a list with the given element type and length. Its
+    /// elements follow until the matching `End`.
+    StartList(TagType, usize),
+    /// A value that's neither a compound nor a list.
+    Value(Tag),
+    /// The end of the innermost compound or list.
+    End
+}
+
+#[derive(Clone, Copy)]
+enum Frame {
+    // Number of entries read so far
+    Compound(usize),
+    // Element type, next index and length
+    List(TagType, usize, usize)
+}
 
-fn read_value<R: Read>(reader: &mut R, vtype: TagType) -> Result<Tag> {
-    match vtype {
-        // Can't read the end marker as an actual tag
-        TagType::End   => Err(Error::Malformed),
+/// Decode a named tag as a stream of events, without building the tree.
+///
+/// Limits set on the wrapped `Decoder`, like the maximum depth and number of
+/// compound entries, apply to the events as well.
+pub struct EventDecoder<R> {
+    decoder: Decoder<R>,
+    stack: Vec<Frame>,
+    pending: Option<TagType>,
+    started: bool
+}
 
-        TagType::Byte   => Ok(Tag::Byte(read_primitive(reader)?)),
-        TagType::Short  => Ok(Tag::Short(read_primitive(reader)?)),
-        TagType::Int    => Ok(Tag::Int(read_primitive(reader)?)),
-        TagType::Long   => Ok(Tag::Long(read_primitive(reader)?)),
-        TagType::Float  => Ok(Tag::Float(read_primitive(reader)?)),
-        TagType::Double => Ok(Tag::Double(read_primitive(reader)?)),
+impl<R: Read> EventDecoder<R> {
+    /// Create an event decoder reading through `decoder`.
+    pub fn new(decoder: Decoder<R>) -> EventDecoder<R> {
+        EventDecoder {
+            decoder,
+            stack: Vec::new(),
+            pending: None,
+            started: false
+        }
+    }
 
-        TagType::ByteArray => {
-            let len = read_primitive::<_, i32>(reader)?;
-            let mut bytes = vec![0_u8; len as usize];
+    /// Create an event decoder for a reader of uncompressed NBT.
+    pub fn from_reader(reader: R) -> EventDecoder<R> {
+        EventDecoder::new(Decoder::from_reader(reader))
+    }
 
-            reader.read(bytes.as_mut_slice())?;
+    /// Return the wrapped decoder.
+    pub fn into_inner(self) -> Decoder<R> {
+        self.decoder
+    }
 
-            Ok(Tag::ByteArray(bytes))
-        },
+    /// Read the next event, or `None` once the root tag has been read.
+    pub fn next_event(&mut self) -> Result<Option<Event>> {
+        if let Some(t) = self.pending.take() {
+            return self.begin(t).map(Some);
+        }
 
-        TagType::String => Ok(Tag::String(read_string(reader)?)),
+        match self.stack.last().cloned() {
+            None => {
+                if self.started {
+                    return Ok(None);
+                }
 
-        TagType::List => {
-            let et = read_primitive::<_, i8>(reader)?;
-            let tt = TagType::from_binary(et as u8);
-            let len = read_primitive::<_, i32>(reader)?;
+                self.started = true;
 
-            if tt.is_none() && et != 0 {
-                return Err(Error::Malformed);
+                let t = self.decoder.read_type()?;
 
-            }
+                if t == TagType::End {
+                    return Ok(None);
+                }
+
+                let name = self.decoder.read_string()?;
+                self.pending = Some(t);
+
+                Ok(Some(Event::Named(name, t)))
+            },
+
+            Some(Frame::Compound(n)) => {
+                let t = self.decoder.read_type()?;
+
+                if t == TagType::End {
+                    self.close();
+                    return Ok(Some(Event::End));
+                }
+
+                if self.decoder.max_compound_entries.is_some_and(|m| n >= m) {
+                    return Err(Error::LimitExceeded(path::format(&self.decoder.path)));
+                }
+
+                *self.stack.last_mut().unwrap() = Frame::Compound(n + 1);
 
-            let mut vec = Vec::with_capacity(len as usize);
+                let name = self.decoder.read_string()?;
+                self.decoder.path.push(Segment::Key(name.clone()));
+                self.pending = Some(t);
 
-            for _ in 0 .. len {
-                vec.push(read_value(reader, tt.unwrap())?);
+                Ok(Some(Event::Named(name, t)))
+            },
+
+            Some(Frame::List(t, i, len)) => {
+                if i == len {
+                    self.close();
+                    return Ok(Some(Event::End));
+                }
+
+                *self.stack.last_mut().unwrap() = Frame::List(t, i + 1, len);
+                self.decoder.path.push(Segment::Index(i));
+
+                self.begin(t).map(Some)
             }
+        }
+    }
 
-            Ok(Tag::List(ListData {
-                element_type: TagType::from_binary(et as u8).unwrap(),
-                elements: vec
-            }))
-        },
+    /// Skip a value without building it.
+    ///
+    /// Right after a `Named` event, this skips the value it announced.
+    /// Otherwise it skips the rest of the innermost compound or list,
+    /// including its `End` event.
+    pub fn skip_current(&mut self) -> Result<()> {
+        if let Some(t) = self.pending.take() {
+            self.decoder.skip_payload(t, 0)?;
+            self.finish();
 
-        TagType::Compound => {
-            let mut map = HashMap::new();
+            return Ok(());
+        }
 
-            loop {
-                match read_tag(reader) {
-                    Ok((_, Tag::End)) => break,
-                    Ok((n, v))        => map.insert(n, v),
-                    Err(e)            => return Err(e)
-                };
+        match self.stack.last().cloned() {
+            None => return Ok(()),
+
+            Some(Frame::Compound(_)) => self.decoder.skip_entries(0)?,
+
+            Some(Frame::List(t, i, len)) => for _ in i .. len {
+                self.decoder.skip_payload(t, 0)?;
             }
+        }
 
-            Ok(Tag::Compound(CompoundData { elements: map }))
-        },
+        self.close();
+
+        Ok(())
+    }
+
+    fn begin(&mut self, t: TagType) -> Result<Event> {
+        if (t == TagType::List || t == TagType::Compound) && self.decoder.path.len() >= self.decoder.max_depth {
+            return Err(Error::LimitExceeded(path::format(&self.decoder.path)));
+        }
 
-        TagType::IntArray => {
-            let len = read_primitive::<_, i32>(reader)?;
-            let mut ints = Vec::with_capacity(len as usize);
+        match t {
+            TagType::Compound => {
+                self.stack.push(Frame::Compound(0));
+                Ok(Event::StartCompound)
+            },
 
-            for _ in 0 .. len {
-                ints.push(read_primitive::<_, i32>(reader)?);
+            TagType::List => {
+                let et = self.decoder.read_type()?;
+                let len = self.decoder.read_len(et.fixed_size().unwrap_or(1))?;
+
+                self.stack.push(Frame::List(et, 0, len));
+                Ok(Event::StartList(et, len))
+            },
+
+            _ => {
+                let v = self.decoder.read_payload(t)?;
+                self.finish();
+
+                Ok(Event::Value(v))
             }
+        }
+    }
+
+    // Leave the innermost compound or list.
+    fn close(&mut self) {
+        self.stack.pop();
+        self.finish();
+    }
+
+    // Leave the path segment of a value that was fully read.
+    fn finish(&mut self) {
+        if !self.stack.is_empty() {
+            self.decoder.path.pop();
+        }
+    }
+}
+
+#[test]
+fn test_read_tag_hashed() {
+    use encode::Encoder;
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.elements.extend(vec![Tag::Double(1.5), Tag::Double(-64.0)]);
+
+    let mut ent = CompoundData::new();
+    ent.insert("Pos".to_owned(), Tag::List(pos));
+
+    let root = Tag::Compound(ent);
+
+    for &e in &[Endianness::Big, Endianness::Little] {
+        let mut data = Vec::new();
+        Encoder::from_writer_with(&mut data, e).write_tag(("ent", &root)).unwrap();
 
-            Ok(Tag::IntArray(ints))
+        let ((name, tag), hash) = Decoder::from_reader_with(Cursor::new(data), e)
+            .read_tag_hashed().unwrap();
+
+        assert_eq!(name, "ent");
+        assert_eq!(tag, root);
+        assert_eq!(hash, root.content_hash());
+    }
+
+    assert!(root.content_hash() != Tag::Compound(CompoundData::new()).content_hash());
+    assert!(Tag::Int(1).content_hash() != Tag::Int(2).content_hash());
+}
+
+#[test]
+fn test_read_auto_fallback() {
+    use std::io::Write;
+    use flate2;
+    use flate2::write::GzEncoder;
+
+    let raw = [10, 0, 0, 1, 0, 1, b'a', 42, 0];
+
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&raw).unwrap();
+    let gz = gz.finish().unwrap();
+
+    assert_eq!(sniff_compression(&gz), Compression::GZip);
+    assert_eq!(sniff_compression(&raw), Compression::Uncompressed);
+
+    let ((_, a), ca) = read_auto(&gz).unwrap();
+    let ((_, b), cb) = read_auto(&raw).unwrap();
+
+    assert_eq!(ca, Compression::GZip);
+    assert_eq!(cb, Compression::Uncompressed);
+    assert_eq!(a, b);
+    assert_eq!(a.get_type(), TagType::Compound);
+
+    // A root string tag whose name length looks like a Zlib header
+    let mut s = vec![8, 0x1D, 0];
+    s.extend(vec![b'n'; 0x1D00]);
+    s.extend(&[0, 2, b'h', b'i']);
+
+    assert_eq!(sniff_compression(&s), Compression::Zlib);
+    assert_eq!(read_auto(&s).unwrap().1, Compression::Uncompressed);
+
+    // Truncated data fails with the error from the sniffed method
+    assert!(read_auto(&gz[.. 12]).is_err());
+    assert!(read_auto(&[0xFF, 0xFF]).is_err());
+}
+
+#[test]
+fn test_read_tag_indexed() {
+    // {"": {a: 42b, l: [1s, 2s], c: {s: "hi"}}}
+    let raw = vec![
+        10, 0, 0,
+            1, 0, 1, b'a', 42,
+            9, 0, 1, b'l', 2, 0, 0, 0, 2, 0, 1, 0, 2,
+            10, 0, 1, b'c',
+                8, 0, 1, b's', 0, 2, b'h', b'i',
+            0,
+        0
+    ];
+
+    let (_, tag, index) =
+        Decoder::from_reader(Cursor::new(raw.clone())).read_tag_indexed().unwrap();
+
+    assert_eq!(index.range(""), Some((3, raw.len() as u64)));
+    assert_eq!(index.range("a"), Some((7, 8)));
+    assert_eq!(index.range("l[1]"), Some((19, 21)));
+    assert_eq!(index.len(), 7);
+
+    let (start, end) = index.range("c").unwrap();
+    let sub = raw[start as usize .. end as usize].to_vec();
+    let c = Decoder::from_reader(Cursor::new(sub)).read_value(TagType::Compound).unwrap();
+
+    match tag {
+        Tag::Compound(ref x) => assert_eq!(x.elements.get("c"), Some(&c)),
+        _                    => panic!("expected a compound")
+    }
+}
+
+#[test]
+fn test_max_compound_entries() {
+    // {"": {c: {a: 1b, b: 2b, c: 3b}}}
+    let raw = vec![
+        10, 0, 0,
+            10, 0, 1, b'c',
+                1, 0, 1, b'a', 1,
+                1, 0, 1, b'b', 2,
+                1, 0, 1, b'c', 3,
+            0,
+        0
+    ];
+
+    let mut dec = Decoder::from_reader(Cursor::new(raw.clone()));
+    dec.set_max_compound_entries(Some(3));
+    assert!(dec.read_tag().is_ok());
+
+    let mut dec = Decoder::from_reader(Cursor::new(raw));
+    dec.set_max_compound_entries(Some(2));
+
+    match dec.read_tag() {
+        Err(Error::LimitExceeded(p)) => assert_eq!(p, "c"),
+        x                            => panic!("unexpected {:?}", x)
+    }
+}
+
+#[cfg(feature = "btreemap")]
+#[test]
+fn test_sorted_compound() {
+    // {"": {b: 1b, c: 2b, a: 3b}}
+    let raw = vec![
+        10, 0, 0,
+            1, 0, 1, b'b', 1,
+            1, 0, 1, b'c', 2,
+            1, 0, 1, b'a', 3,
+        0
+    ];
+
+    match Decoder::from_reader(Cursor::new(raw)).read_tag().unwrap().1 {
+        Tag::Compound(ref c) => {
+            let keys: Vec<&str> = c.keys().map(|k| &k[..]).collect();
+            assert_eq!(keys, vec!["a", "b", "c"]);
         },
+        _ => panic!("expected a compound")
+    }
+}
 
-        TagType::LongArray => {
-            let len = read_primitive::<_, i64>(reader)?;
-            let mut ints = Vec::with_capacity(len as usize);
+#[test]
+fn test_max_initial_reserve() {
+    let n = 100_000_usize;
 
-            for _ in 0 .. len {
-                ints.push(read_primitive::<_, i64>(reader)?);
-            }
+    let mut raw = vec![11, 0, 0, (n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8];
+    raw.extend((0 .. n).flat_map(|i| vec![0, 0, (i >> 8) as u8, i as u8]));
 
-            Ok(Tag::LongArray(ints))
+    let mut dec = Decoder::from_reader(Cursor::new(raw));
+    dec.set_max_initial_reserve(1000);
+
+    assert_eq!(dec.reserve(n), 1000);
+    assert_eq!(dec.reserve(10), 10);
+
+    let ints = dec.read_tag().unwrap().1;
+    let ints = ints.as_int_array().unwrap();
+
+    assert_eq!(ints.len(), n);
+    assert_eq!(ints[n - 1], (n - 1) as i32 & 0xFFFF);
+}
+
+#[test]
+fn test_skip_magic() {
+    let mut raw = b"NBT1".to_vec();
+    raw.extend_from_slice(&[10, 0, 0, 1, 0, 1, b'a', 42, 0]);
+
+    let mut dec = Decoder::from_reader(Cursor::new(raw.clone()));
+    dec.skip_magic(b"NBT1").unwrap();
+
+    match dec.read_tag().unwrap().1 {
+        Tag::Compound(ref c) => assert_eq!(c.elements.get("a"), Some(&Tag::Byte(42))),
+        _                    => panic!("expected a compound")
+    }
+
+    let mut dec = Decoder::from_reader(Cursor::new(raw));
+    assert!(dec.skip_magic(b"NBT2").is_err());
+}
+
+#[test]
+fn test_short_reads() {
+    // A reader that hands out a single byte per call, like a slow socket
+    struct Trickle(Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(1);
+            self.0.read(&mut buf[.. n])
         }
     }
+
+    let raw = vec![
+        10, 0, 0,
+            8, 0, 1, b's', 0, 5, b'h', b'e', b'l', b'l', b'o',
+            11, 0, 1, b'i', 0, 0, 0, 2, 0, 0, 1, 0, 0, 0, 0, 2,
+        0
+    ];
+
+    let short = Decoder::from_reader(Trickle(Cursor::new(raw.clone()))).read_tag().unwrap();
+    let whole = Decoder::from_reader(Cursor::new(raw.clone())).read_tag().unwrap();
+
+    assert_eq!(short, whole);
+
+    match short.1 {
+        Tag::Compound(ref c) => {
+            assert_eq!(c.elements.get("s"), Some(&Tag::String("hello".to_owned())));
+            assert_eq!(c.elements.get("i"), Some(&Tag::IntArray(vec![256, 2])));
+        },
+        _ => panic!("expected a compound")
+    }
+
+    match Decoder::from_reader(Cursor::new(raw[.. 12].to_vec())).read_tag() {
+        Err(Error::UnexpectedEof) => (),
+        x                         => panic!("unexpected {:?}", x)
+    }
 }
 
-fn read_tag<R: Read>(reader: &mut R) -> Result<(String, Tag)> {
-    let mut header = [0_u8; 1];
-    reader.read(&mut header)?;
+#[test]
+fn test_progress() {
+    use std::fs;
+    use util;
 
-    if let Some(t) = TagType::from_binary(header[0]) {
-        if t == TagType::End {
-            return Ok(("".to_owned(), Tag::End));
+    let raw = vec![10, 0, 0, 1, 0, 1, b'a', 5, 3, 0, 1, b'b', 0, 0, 0, 7, 0];
+
+    let dir = util::test_dir("progress");
+    let file = dir.join("progress.nbt");
+    let file = file.to_str().unwrap();
+    fs::write(file, &raw).unwrap();
+
+    let seen = Rc::new(Cell::new((0, None)));
+    let s = seen.clone();
+
+    let mut dec = Decoder::from_file(file, Compression::Uncompressed).unwrap();
+    dec.set_progress(move |read, total| s.set((read, total)));
+    dec.read_tag().unwrap();
+
+    assert_eq!(seen.get(), (raw.len() as u64, Some(raw.len() as u64)));
+
+    // Readers without a known length report it once it's set
+    let s = seen.clone();
+
+    let mut dec = Decoder::from_reader(Cursor::new(raw.clone()));
+    dec.set_progress(move |read, total| s.set((read, total)));
+    dec.set_total_len(raw.len() as u64);
+    dec.read_tag().unwrap();
+
+    assert_eq!(seen.get(), (raw.len() as u64, Some(raw.len() as u64)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_auto_compression() {
+    use std::io::Write;
+    use flate2;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+
+    let raw = vec![10, 0, 1, b'r', 3, 0, 1, b'x', 0, 0, 0, 9, 0];
+    let expected = ("r".to_owned(), Decoder::from_reader(Cursor::new(raw.clone())).read_tag().unwrap().1);
+
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&raw).unwrap();
+    let gz = gz.finish().unwrap();
+
+    let mut z = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    z.write_all(&raw).unwrap();
+    let z = z.finish().unwrap();
+
+    for data in [raw, gz, z] {
+        let mut dec = Decoder::from_reader_compressed(Cursor::new(data), Compression::Auto);
+        assert_eq!(dec.read_tag().unwrap(), expected);
+    }
+
+    let mut dec = Decoder::from_reader_compressed(Cursor::new(vec![10]), Compression::Auto);
+    assert!(dec.read_tag().is_err());
+}
+
+#[test]
+fn test_interner() {
+    use encode;
+    use super::CompoundData;
+
+    let block = |n: i32| {
+        let mut c = CompoundData::new();
+        c.insert("Name".to_owned(), Tag::String("minecraft:stone".to_owned()));
+        c.insert("n".to_owned(), Tag::Int(n));
+        encode::to_bytes("", &Tag::Compound(c)).unwrap()
+    };
+
+    let interner = Rc::new(RefCell::new(StringInterner::new()));
+
+    for data in [block(1), block(2)] {
+        let mut dec = Decoder::from_reader(Cursor::new(data));
+        dec.set_interner(interner.clone());
+        dec.read_tag().unwrap();
+    }
+
+    // "", "Name", "n" and "minecraft:stone", each once
+    assert_eq!(interner.borrow().len(), 4);
+
+    let a = interner.borrow().get("minecraft:stone").unwrap();
+    let b = interner.borrow_mut().intern("minecraft:stone");
+    assert!(Rc::ptr_eq(&a, &b));
+}
+
+#[test]
+fn test_borrowed_reader() {
+    use encode;
+
+    let mut data = encode::to_bytes("a", &Tag::Int(1)).unwrap();
+    data.extend(encode::to_bytes("b", &Tag::Short(2)).unwrap());
+
+    let mut cursor = Cursor::new(data);
+
+    assert_eq!(Decoder::from_reader(&mut cursor).read_tag().unwrap(), ("a".to_owned(), Tag::Int(1)));
+    assert_eq!(cursor.position(), 8);
+    assert_eq!(Decoder::from_reader(&mut cursor).read_tag().unwrap(), ("b".to_owned(), Tag::Short(2)));
+}
+
+#[test]
+fn test_event_decoder() {
+    use encode;
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.elements.extend(vec![Tag::Double(1.5), Tag::Double(-64.0)]);
+
+    let mut level = CompoundData::new();
+    level.insert("Pos".to_owned(), Tag::List(pos));
+
+    let mut root = CompoundData::new();
+    root.insert("Level".to_owned(), Tag::Compound(level));
+
+    let data = encode::to_bytes("", &Tag::Compound(root)).unwrap();
+    let mut events = EventDecoder::from_reader(Cursor::new(data.clone()));
+    let mut seen = Vec::new();
+
+    while let Some(e) = events.next_event().unwrap() {
+        seen.push(e);
+    }
+
+    assert_eq!(seen, [
+        Event::Named("".to_owned(), TagType::Compound),
+        Event::StartCompound,
+        Event::Named("Level".to_owned(), TagType::Compound),
+        Event::StartCompound,
+        Event::Named("Pos".to_owned(), TagType::List),
+        Event::StartList(TagType::Double, 2),
+        Event::Value(Tag::Double(1.5)),
+        Event::Value(Tag::Double(-64.0)),
+        Event::End,
+        Event::End,
+        Event::End
+    ]);
+
+    // Skipping the announced value and the rest of a started list
+    let mut events = EventDecoder::from_reader(Cursor::new(data.clone()));
+
+    for _ in 0 .. 3 { events.next_event().unwrap(); }
+    events.skip_current().unwrap();
+    assert_eq!(events.next_event().unwrap(), Some(Event::End));
+    assert_eq!(events.next_event().unwrap(), None);
+
+    let mut events = EventDecoder::from_reader(Cursor::new(data));
+
+    for _ in 0 .. 7 { events.next_event().unwrap(); }
+    events.skip_current().unwrap();
+    assert_eq!(events.next_event().unwrap(), Some(Event::End));
+    assert_eq!(events.next_event().unwrap(), Some(Event::End));
+    assert_eq!(events.next_event().unwrap(), None);
+}
+
+#[test]
+fn test_skip_value() {
+    use encode;
+
+    let mut sections = ListData::new(TagType::Compound);
+
+    for y in 0 .. 3 {
+        let mut s = CompoundData::new();
+        s.insert("Y".to_owned(), Tag::Byte(y));
+        s.insert("BlockStates".to_owned(), Tag::LongArray(vec![0; 256]));
+        s.insert("Name".to_owned(), Tag::String("section".to_owned()));
+        sections.elements.push(Tag::Compound(s));
+    }
+
+    let mut data = encode::to_bytes("Sections", &Tag::List(sections)).unwrap();
+    data.extend(encode::to_bytes("Heights", &Tag::IntArray(vec![1, 2, 3])).unwrap());
+    data.extend(encode::to_bytes("xPos", &Tag::Int(7)).unwrap());
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+
+    dec.skip_tag().unwrap();
+
+    assert_eq!(dec.read_type().unwrap(), TagType::IntArray);
+    assert_eq!(dec.read_string().unwrap(), "Heights");
+    dec.skip_value(TagType::IntArray).unwrap();
+
+    assert_eq!(dec.read_tag().unwrap(), ("xPos".to_owned(), Tag::Int(7)));
+}
+
+#[test]
+fn test_read_network_frame() {
+    use std::io::Write;
+    use flate2;
+    use flate2::write::ZlibEncoder;
+    use encode;
+
+    fn var_int(mut x: usize, out: &mut Vec<u8>) {
+        while x >= 0x80 {
+            out.push((x & 0x7F) as u8 | 0x80);
+            x >>= 7;
         }
 
-        let name = read_string(reader)?;
-        let v = read_value(reader, t)?;
+        out.push(x as u8);
+    }
+
+    let mut c = CompoundData::new();
+    c.insert("text".to_owned(), Tag::String("x".repeat(200)));
+    let root = Tag::Compound(c);
 
-        Ok((name, v))
+    let raw = encode::to_bytes("", &root).unwrap();
+
+    let mut z = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    z.write_all(&raw).unwrap();
+
+    let mut body = Vec::new();
+    var_int(raw.len(), &mut body);
+    body.extend(z.finish().unwrap());
+
+    let mut data = Vec::new();
+    var_int(body.len(), &mut data);
+    data.extend(&body);
+
+    // An uncompressed frame follows, flagged with an uncompressed length of 0
+    let small = encode::to_bytes("n", &Tag::Int(1)).unwrap();
+    var_int(small.len() + 1, &mut data);
+    data.push(0);
+    data.extend(&small);
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    dec.set_frame_compression(true);
+
+    assert_eq!(dec.read_network_frame().unwrap(), ("".to_owned(), root));
+    assert_eq!(dec.read_network_frame().unwrap(), ("n".to_owned(), Tag::Int(1)));
+
+    let mut data = Vec::new();
+    var_int(small.len(), &mut data);
+    data.extend(&small);
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    assert_eq!(dec.read_network_frame().unwrap(), ("n".to_owned(), Tag::Int(1)));
+
+    let mut dec = Decoder::from_reader(Cursor::new(vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]));
+    assert!(dec.read_network_frame().is_err());
+
+    // The declared length must match what the frame inflates to
+    let compressed = {
+        let mut z = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        z.write_all(&raw).unwrap();
+        z.finish().unwrap()
+    };
+
+    for &declared in &[raw.len() - 1, raw.len() + 1] {
+        let mut body = Vec::new();
+        var_int(declared, &mut body);
+        body.extend(&compressed);
+
+        let mut data = Vec::new();
+        var_int(body.len(), &mut data);
+        data.extend(&body);
+
+        let mut dec = Decoder::from_reader(Cursor::new(data));
+        dec.set_frame_compression(true);
+
+        match dec.read_network_frame() {
+            Err(Error::Malformed) => (),
+            other                 => panic!("expected Malformed, got {:?}", other)
+        }
+    }
 
-    } else {
-        return Err(Error::Malformed)
+    // Progress reports keep coming while the frame's contents are decoded
+    let mut data = Vec::new();
+    var_int(small.len(), &mut data);
+    data.extend(&small);
+    let len = data.len() as u64;
+
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let r = reports.clone();
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    dec.set_progress(move |read, _| r.borrow_mut().push(read));
+    assert_eq!(dec.read_network_frame().unwrap(), ("n".to_owned(), Tag::Int(1)));
+
+    // One report for the frame length and one for its contents, then the
+    // nested reads, all at the end of the frame
+    let reports = reports.borrow();
+    assert!(reports.len() > 2);
+    assert!(reports[1 ..].iter().all(|&read| read == len));
+}
+
+#[test]
+fn test_string_decoder() {
+    // {"caf\xe9": "na\xefve"} in Latin-1
+    let data = vec![8, 0, 4, b'c', b'a', b'f', 0xE9, 0, 5, b'n', b'a', 0xEF, b'v', b'e'];
+
+    assert!(Decoder::from_reader(Cursor::new(data.clone())).read_tag().is_err());
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    dec.set_string_decoder(|b: &[u8]| Ok(b.iter().map(|&c| c as char).collect()));
+
+    assert_eq!(dec.read_tag().unwrap(), ("caf\u{e9}".to_owned(), Tag::String("na\u{ef}ve".to_owned())));
+}
+
+#[test]
+fn test_bulk_arrays() {
+    use encode::Encoder;
+
+    let ints: Vec<i32> = (0 .. 1000).map(|i: i32| i.wrapping_mul(0x0101_0101)).collect();
+    let longs: Vec<i64> = (0 .. 1000).map(|i: i64| i.wrapping_mul(0x0102_0304_0506_0708)).collect();
+
+    for &e in &[Endianness::Big, Endianness::Little] {
+        for t in [Tag::IntArray(ints.clone()), Tag::LongArray(longs.clone())] {
+            let mut data = Vec::new();
+            Encoder::from_writer_with(&mut data, e).write_tag(("a", &t)).unwrap();
+
+            // A small reserve splits the array over many reads
+            let mut dec = Decoder::from_reader_with(Cursor::new(data.clone()), e);
+            dec.set_max_initial_reserve(7);
+
+            let ((_, read), hash) = dec.read_tag_hashed().unwrap();
+            assert_eq!(read, t);
+            assert_eq!(hash, t.content_hash());
+
+            let mut again = Vec::new();
+            Encoder::from_writer_with(&mut again, e).write_tag(("a", &read)).unwrap();
+            assert_eq!(again, data);
+        }
     }
+}
+
+#[test]
+fn test_detect_legacy() {
+    use encode;
+    use snbt::parse_snbt;
+
+    let root = parse_snbt("{Entities: [{OwnerUUIDMost: 1L, OwnerUUIDLeast: 2L, UUID: [I; 1, 2, 3, 4]}], \
+                           Item: {id: 276s, Count: 1b}, Name: {id: \"minecraft:stone\"}}").unwrap();
+    let data = encode::to_bytes("", &root).unwrap();
+
+    let mut dec = Decoder::from_reader(Cursor::new(data.clone()));
+    dec.read_tag().unwrap();
+    assert!(dec.warnings().is_empty());
 
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    dec.set_detect_legacy(true);
+    dec.read_tag().unwrap();
+
+    // Compounds are written in map order, so sort for a stable comparison
+    let mut warnings = dec.warnings().to_vec();
+    warnings.sort_by(|a, b| a.path.cmp(&b.path));
+
+    assert_eq!(warnings, [
+        Warning { path: "Entities[0].OwnerUUIDMost".to_owned(), kind: Legacy::UuidPair },
+        Warning { path: "Item.id".to_owned(), kind: Legacy::NumericId }
+    ]);
 }
 
-/// Decode NBT tags.
-pub struct Decoder {
-    reader: Box<Read>
+#[test]
+fn test_decode_prefix() {
+    use encode;
+
+    let mut c = CompoundData::new();
+    c.insert("id".to_owned(), Tag::Int(7));
+    let root = Tag::Compound(c);
+
+    let mut data = encode::to_bytes("", &root).unwrap();
+    data.extend(&[1, 2, 3, 4]);
+
+    let ((name, tag), tail) = decode_prefix(&data).unwrap();
+
+    assert_eq!(name, "");
+    assert_eq!(tag, root);
+    assert_eq!(tail, [1, 2, 3, 4]);
 }
 
-impl Decoder {
-    /// Create a new Decoder from an existing reader that will be taken
-    /// ownership over.
-    pub fn from_reader<R: Read + 'static>(reader: R) -> Decoder {
-        Decoder {
-            reader: Box::new(reader)
+#[test]
+fn test_hostile_lengths() {
+    let decode = |raw: Vec<u8>, max: Option<usize>| {
+        let mut dec = Decoder::from_reader(Cursor::new(raw));
+
+        if let Some(m) = max {
+            dec.set_max_alloc(m);
+        }
+
+        dec.read_tag()
+    };
+
+    // Negative lengths for a byte array, an int list and a long array
+    for t in &[7_u8, 9, 12] {
+        let mut raw = vec![*t, 0, 0];
+
+        if *t == 9 {
+            raw.push(3);
+        }
+
+        raw.extend(&[0xFF, 0xFF, 0xFF, 0xFE]);
+
+        match decode(raw, None) {
+            Err(Error::Malformed) => (),
+            x                     => panic!("unexpected {:?}", x)
         }
     }
 
-    pub fn from_file(file: &str, c: Compression) -> Result<Decoder> {
-        Ok(Decoder {
-            reader: match c {
-                Compression::Uncompressed => Box::new(File::open(file)?),
-                Compression::GZip =>
-                    Box::new(GzDecoder::new(File::open(file)?))
-            }
-        })
+    // An int array claiming 0x7FFFFFFF elements
+    match decode(vec![11, 0, 0, 0x7F, 0xFF, 0xFF, 0xFF], None) {
+        Err(Error::LimitExceeded(_)) => (),
+        x                            => panic!("unexpected {:?}", x)
     }
 
-    /// Read a named tag from the stream.
-    pub fn read_tag(&mut self) -> Result<(String, Tag)> {
-        read_tag(&mut self.reader)
+    // Within the limit it's just truncated input
+    match decode(vec![11, 0, 0, 0, 0, 0, 4, 0, 0, 0, 1], Some(16)) {
+        Err(Error::UnexpectedEof) => (),
+        x                         => panic!("unexpected {:?}", x)
+    }
+
+    match decode(vec![11, 0, 0, 0, 0, 0, 5, 0, 0, 0, 1], Some(16)) {
+        Err(Error::LimitExceeded(_)) => (),
+        x                            => panic!("unexpected {:?}", x)
+    }
+
+    // String lengths are unsigned
+    let mut raw = vec![8, 0, 0, 0x80, 0x00];
+    raw.extend(vec![b'a'; 0x8000]);
+    assert_eq!(decode(raw, None).unwrap().1, Tag::String("a".repeat(0x8000)));
+}
+
+#[test]
+fn test_max_depth() {
+    // `levels` lists nested in each other
+    let nested = |levels: usize| {
+        let mut raw = vec![9, 0, 0];
+
+        for _ in 1 .. levels {
+            raw.extend(&[9, 0, 0, 0, 1]);
+        }
+
+        raw.extend(&[0, 0, 0, 0, 0]);
+        raw
+    };
+
+    let mut dec = Decoder::from_reader(Cursor::new(nested(10)));
+    dec.set_max_depth(10);
+    assert!(dec.read_tag().is_ok());
+
+    let mut dec = Decoder::from_reader(Cursor::new(nested(10)));
+    dec.set_max_depth(9);
+
+    match dec.read_tag() {
+        Err(Error::LimitExceeded(p)) => assert_eq!(p, "[0][0][0][0][0][0][0][0][0]"),
+        x                            => panic!("unexpected {:?}", x)
+    }
+
+    // Deep enough to overflow the stack without the default limit
+    match Decoder::from_reader(Cursor::new(nested(1_000_000))).read_tag() {
+        Err(Error::LimitExceeded(_)) => (),
+        x                            => panic!("unexpected {:?}", x)
     }
 }
 
+#[test]
+fn test_deadline() {
+    use std::time::Duration;
+    use encode;
+
+    let mut l = ListData::new(TagType::Compound);
+
+    for i in 0 .. 10000 {
+        let mut c = CompoundData::new();
+        c.insert("i".to_owned(), Tag::Int(i));
+        l.elements.push(Tag::Compound(c));
+    }
+
+    let data = encode::to_bytes("", &Tag::List(l)).unwrap();
+
+    let mut dec = Decoder::from_reader(Cursor::new(data.clone()));
+    dec.set_deadline(Some(Instant::now()));
+
+    match dec.read_tag() {
+        Err(Error::TimedOut) => (),
+        x                    => panic!("unexpected {:?}", x)
+    }
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    dec.set_deadline(Some(Instant::now() + Duration::from_secs(3600)));
+    assert!(dec.read_tag().is_ok());
+}
@@ -0,0 +1,212 @@
+//! Compare two tags structurally, and patch one with another.
+
+use super::{Error, Result, Tag, CompoundData};
+use decode;
+use path;
+
+/// A single difference between two tags, found by `diff`.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a> {
+    /// A compound entry or list element only present in the second tag.
+    Added(String, &'a Tag),
+    /// A compound entry or list element only present in the first tag.
+    Removed(String, &'a Tag),
+    /// A value that differs between the two tags.
+    Changed(String, &'a Tag, &'a Tag)
+}
+
+fn diff_at<'a>(a: &'a Tag, b: &'a Tag, p: &str, out: &mut Vec<Change<'a>>) {
+    match (a, b) {
+        (Tag::Compound(x), Tag::Compound(y)) => {
+            let mut keys: Vec<&String> = x.elements.keys()
+                .chain(y.elements.keys().filter(|k| !x.elements.contains_key(*k)))
+                .collect();
+
+            keys.sort();
+
+            for k in keys {
+                let child = path::join_key(p, k);
+
+                match (x.elements.get(k), y.elements.get(k)) {
+                    (Some(v), Some(w)) => diff_at(v, w, &child, out),
+                    (Some(v), None)    => out.push(Change::Removed(child, v)),
+                    (None, Some(w))    => out.push(Change::Added(child, w)),
+                    (None, None)       => unreachable!()
+                }
+            }
+        },
+
+        (Tag::List(x), Tag::List(y)) if x.element_type == y.element_type => {
+            for i in 0 .. x.elements.len().max(y.elements.len()) {
+                let child = path::join_index(p, i);
+
+                match (x.elements.get(i), y.elements.get(i)) {
+                    (Some(v), Some(w)) => diff_at(v, w, &child, out),
+                    (Some(v), None)    => out.push(Change::Removed(child, v)),
+                    (None, Some(w))    => out.push(Change::Added(child, w)),
+                    (None, None)       => unreachable!()
+                }
+            }
+        },
+
+        _ => if a != b {
+            out.push(Change::Changed(p.to_owned(), a, b));
+        }
+    }
+}
+
+/// List the differences between `a` and `b`.
+///
+/// Compounds are compared key by key in sorted order, and lists of the same
+/// element type element by element. Any other mismatch, including a change
+/// of type, is reported as a single change of the whole value.
+pub fn diff<'a>(a: &'a Tag, b: &'a Tag) -> Vec<Change<'a>> {
+    let mut out = Vec::new();
+    diff_at(a, b, "", &mut out);
+
+    out
+}
+
+// Short, single line rendering of a value for reports.
+fn summary(t: &Tag) -> String {
+    match *t {
+        Tag::End              => "end".to_owned(),
+        Tag::Byte(x)          => x.to_string(),
+        Tag::Short(x)         => x.to_string(),
+        Tag::Int(x)           => x.to_string(),
+        Tag::Long(x)          => x.to_string(),
+        Tag::Float(x)         => format!("{:?}", x),
+        Tag::Double(x)        => format!("{:?}", x),
+        Tag::String(ref x)    => format!("{:?}", x),
+        Tag::ByteArray(ref x) => format!("[{} bytes]", x.len()),
+        Tag::IntArray(ref x)  => format!("[{} ints]", x.len()),
+        Tag::LongArray(ref x) => format!("[{} longs]", x.len()),
+        Tag::List(ref x)      => format!("[{} elements]", x.elements.len()),
+        Tag::Compound(ref x)  => format!("{{{} entries}}", x.elements.len())
+    }
+}
+
+/// Describe the differences between `a` and `b` as human-readable lines.
+///
+/// Each line is one of `+ path` for additions, `- path` for removals and
+/// `~ path: old -> new` for changed values, as found by `diff`.
+pub fn diff_report(a: &Tag, b: &Tag) -> String {
+    let mut report = String::new();
+
+    for c in diff(a, b) {
+        let line = match c {
+            Change::Added(p, _)      => format!("+ {}", p),
+            Change::Removed(p, _)    => format!("- {}", p),
+            Change::Changed(p, x, y) => format!("~ {}: {} -> {}", p, summary(x), summary(y))
+        };
+
+        report.push_str(&line);
+        report.push('\n');
+    }
+
+    report
+}
+
+// Merge the entries of `patch` into `target`, recursing into compounds
+// present in both.
+fn merge(target: &mut CompoundData, patch: CompoundData) {
+    for (k, v) in patch.elements {
+        match (target.elements.get_mut(&k), v) {
+            (Some(&mut Tag::Compound(ref mut t)), Tag::Compound(p)) => merge(t, p),
+            (_, v) => { target.elements.insert(k, v); }
+        }
+    }
+}
+
+impl Tag {
+    /// Merge a patch into the compound, given as an encoded named tag
+    /// holding a compound.
+    ///
+    /// Compounds in the patch are merged into compounds at the same key,
+    /// recursively, and every other value in the patch replaces the value at
+    /// its key. Fails with `Error::Invalid` without changing anything if
+    /// either the tag or the patch isn't a compound, and with the decoding
+    /// error if the patch can't be decoded.
+    pub fn apply_patch(&mut self, patch: &[u8]) -> Result<()> {
+        let (_, patch) = decode::from_bytes(patch)?;
+
+        match (self, patch) {
+            (&mut Tag::Compound(ref mut t), Tag::Compound(p)) => merge(t, p),
+            _                                                => return Err(Error::Invalid)
+        }
+
+        Ok(())
+    }
+
+    /// Apply `patches` in order with `apply_patch`, all or nothing.
+    ///
+    /// If any patch fails, the tag is restored to its state before the call
+    /// and the error is returned.
+    pub fn apply_patches(&mut self, patches: &[&[u8]]) -> Result<()> {
+        let backup = self.clone();
+
+        for p in patches {
+            if let Err(e) = self.apply_patch(p) {
+                *self = backup;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_apply_patches() {
+    use encode;
+    use snbt::parse_snbt;
+
+    let patch = |s: &str| encode::to_bytes("", &parse_snbt(s).unwrap()).unwrap();
+
+    let orig = parse_snbt("{Player: {Health: 20.0f, Name: \"Steve\"}, Time: 0L}").unwrap();
+    let mut root = orig.clone();
+
+    let good = patch("{Player: {Health: 10.0f}}");
+    let bad = patch("[1, 2]");
+    let last = patch("{Time: 5L}");
+
+    match root.apply_patches(&[&good, &bad, &last]) {
+        Err(Error::Invalid) => (),
+        r                   => panic!("unexpected {:?}", r)
+    }
+
+    assert_eq!(root, orig);
+    assert!(root.apply_patches(&[&good, &[10, 0]]).is_err());
+    assert_eq!(root, orig);
+
+    root.apply_patches(&[&good, &last]).unwrap();
+    assert_eq!(root, parse_snbt("{Player: {Health: 10.0f, Name: \"Steve\"}, Time: 5L}").unwrap());
+}
+
+#[test]
+fn test_diff_report() {
+    use super::CompoundData;
+
+    let level = |health: f32, extra: (&str, Tag)| {
+        let mut player = CompoundData::new();
+        player.insert("Health".to_owned(), Tag::Float(health));
+        player.insert("Name".to_owned(), Tag::String("Steve".to_owned()));
+
+        let mut level = CompoundData::new();
+        level.insert("Player".to_owned(), Tag::Compound(player));
+        level.insert(extra.0.to_owned(), extra.1);
+
+        let mut root = CompoundData::new();
+        root.insert("Level".to_owned(), Tag::Compound(level));
+
+        Tag::Compound(root)
+    };
+
+    let a = level(20.0, ("OldKey", Tag::Byte(1)));
+    let b = level(18.0, ("NewKey", Tag::Byte(1)));
+
+    assert_eq!(diff(&a, &a), vec![]);
+    assert_eq!(diff_report(&a, &b), "+ Level.NewKey\n\
+                                     - Level.OldKey\n\
+                                     ~ Level.Player.Health: 20.0 -> 18.0\n");
+}
@@ -1,127 +1,880 @@
 //! Encode NBT values and write them to files or other writable sinks.
 
-use super::{Error, Result, Tag, Encodable, Compression};
+use super::{Error, Result, Tag, TagType, Encodable, Compression, Endianness};
+use mutf8;
+use util::ContentHasher;
 
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::hash::Hasher;
+use std::io::{self, BufWriter, Write};
 
-use flate2::write::GzEncoder;
+use flate2::write::{GzEncoder, ZlibEncoder};
 use flate2;
 
+/// Encode the named tag `(name, tag)` into a new byte buffer.
+pub fn to_bytes(name: &str, tag: &Tag) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    Encoder::from_writer(&mut buf).write_tag((name, tag))?;
 
-fn write_primitive<W: Write, T: Encodable>(writer: &mut W, i: T) -> Result<()> {
-    Ok(writer.write(&i.to_bytes_nbt()).map(|_| ())?)
+    Ok(buf)
 }
 
-fn write_string<W: Write>(writer: &mut W, s: &str) -> Result<()> {
-    write_primitive(writer, s.len() as i16)?;
+impl Tag {
+    /// Encode the tag named `name` into a new uncompressed byte buffer, like
+    /// `to_bytes`.
+    pub fn to_bytes(&self, name: &str) -> Result<Vec<u8>> {
+        to_bytes(name, self)
+    }
+
+    /// Return a 64 bit FNV-1a hash of the tag's encoded payload.
+    ///
+    /// The payload is hashed as it would be written big endian, without the
+    /// type ID and name of a named tag. Compound entries are hashed in the
+    /// compound's iteration order, so the hash only matches between equal
+    /// compounds with the same order, e.g. with the `btreemap` feature.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = ContentHasher::new();
 
-    Ok(writer.write(s.as_bytes()).map(|_| ())?)
+        // An `End` tag has no payload, and hashing never fails otherwise
+        if *self != Tag::End {
+            Encoder::from_writer(&mut hasher).write_value(self, true).unwrap();
+        }
+
+        hasher.finish()
+    }
 }
 
-fn write_value<W: Write>(writer: &mut W, tag: &Tag) -> Result<()> {
-    match *tag {
-        Tag::End       => return Err(Error::Invalid),
-        Tag::Byte(x)   => write_primitive(writer, x)?,
-        Tag::Short(x)  => write_primitive(writer, x)?,
-        Tag::Int(x)    => write_primitive(writer, x)?,
-        Tag::Long(x)   => write_primitive(writer, x)?,
-        Tag::Float(x)  => write_primitive(writer, x)?,
-        Tag::Double(x) => write_primitive(writer, x)?,
+/// A writer that needs finalizing once all data has been written, like a
+/// compressed stream that ends with a footer.
+pub trait FinishWrite: Write {
+    /// Write out any trailing data and flush.
+    fn finish_write(&mut self) -> io::Result<()>;
+}
 
-        Tag::ByteArray(ref x) => {
-            write_primitive(writer, x.len() as i32)?;
-            writer.write(&*x)?;
-        },
+impl<W: Write> FinishWrite for GzEncoder<W> {
+    fn finish_write(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> FinishWrite for ZlibEncoder<W> {
+    fn finish_write(&mut self) -> io::Result<()> {
+        self.try_finish()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: FinishWrite + ?Sized> FinishWrite for Box<W> {
+    fn finish_write(&mut self) -> io::Result<()> {
+        (**self).finish_write()
+    }
+}
+
+// An uncompressed stream, which only needs flushing.
+struct Plain<W>(W);
+
+impl<W: Write> Write for Plain<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write> FinishWrite for Plain<W> {
+    fn finish_write(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Encode NBT tags.
+pub struct Encoder<W> {
+    writer: W,
+    round_floats: Option<u32>,
+    auto_compact_arrays: bool,
+    endianness: Endianness
+}
+
+impl Encoder<Box<dyn FinishWrite>> {
+    /// Create a new Encoder from an existing writer, compressing the
+    /// written data with the given compression method.
+    ///
+    /// Call `finish` once done, to write the end of the compressed stream
+    /// and see any errors.
+    pub fn from_writer_compressed<S: Write + 'static>(writer: S, c: Compression) -> Encoder<Box<dyn FinishWrite>> {
+        let writer: Box<dyn FinishWrite> = match c {
+            Compression::Uncompressed | Compression::Auto =>
+                Box::new(Plain(writer)),
+            Compression::GZip =>
+                Box::new(GzEncoder::new(writer, flate2::Compression::default())),
+            Compression::GZipStored =>
+                Box::new(GzEncoder::new(writer, flate2::Compression::none())),
+            Compression::Zlib =>
+                Box::new(ZlibEncoder::new(writer, flate2::Compression::default()))
+        };
+
+        Encoder::from_writer(writer)
+    }
+
+    /// Create a new Encoder from an existing writer, like
+    /// `from_writer_compressed`, but buffer the writes to it.
+    ///
+    /// Encoding issues many small writes, so this helps with writers that
+    /// are slow to call, like files and sockets. Call `finish` once done to
+    /// write out the buffer and see any errors.
+    pub fn from_writer_buffered<S: Write + 'static>(writer: S, c: Compression) -> Encoder<Box<dyn FinishWrite>> {
+        Encoder::from_writer_compressed(BufWriter::new(writer), c)
+    }
+
+    /// Create a new Encoder for the given file `file`, with the given
+    /// compression method.
+    ///
+    /// Writes to the file are buffered as with `from_writer_buffered`, so
+    /// call `finish` once done to see any errors.
+    pub fn from_file(file: &str, c: Compression) -> Result<Encoder<Box<dyn FinishWrite>>> {
+        let f = OpenOptions::new()
+          .create(true)
+          .truncate(true)
+          .write(true)
+          .open(file)?;
+
+        Ok(Encoder::from_writer_buffered(f, c))
+    }
+}
 
-        Tag::String(ref x) => write_string(writer, x)?,
-        Tag::List(ref x) => {
-            write_primitive(writer, x.element_type.to_binary() as i8)?;
-            write_primitive(writer, x.elements.len() as i32)?;
+impl<W: FinishWrite> Encoder<W> {
+    /// Finalize the stream, like writing the footer of a gzip stream, and
+    /// flush it.
+    ///
+    /// Dropping the encoder also tries to finalize compressed streams, but
+    /// ignores any errors doing so.
+    pub fn finish(mut self) -> Result<()> {
+        Ok(self.writer.finish_write()?)
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    /// Create a new Encoder from an existing writer that will be taken
+    /// ownership over.
+    pub fn from_writer(writer: W) -> Encoder<W> {
+        Encoder {
+            writer,
+            round_floats: None,
+            auto_compact_arrays: false,
+            endianness: Endianness::Big
+        }
+    }
+
+    /// Create a new Encoder from an existing writer, writing numbers in the
+    /// given byte order.
+    pub fn from_writer_with(writer: W, e: Endianness) -> Encoder<W> {
+        let mut encoder = Encoder::from_writer(writer);
+        encoder.endianness = e;
+        encoder
+    }
+
+    /// Round `Float` and `Double` values to the given number of decimal
+    /// places before writing them, or write them exactly if `None` (the
+    /// default).
+    pub fn set_round_floats(&mut self, decimals: Option<u32>) {
+        self.round_floats = decimals;
+    }
+
+    /// Write lists whose elements are all bytes, ints or longs as the
+    /// matching array tag, without changing the tag being written (off by
+    /// default).
+    ///
+    /// Note that this changes the type seen by the decoder on the other
+    /// side: such a list is read back as a `ByteArray`, `IntArray` or
+    /// `LongArray`.
+    pub fn set_auto_compact_arrays(&mut self, compact: bool) {
+        self.auto_compact_arrays = compact;
+    }
+
+    /// Write a named tag to the stream.
+    ///
+    /// The stream isn't flushed, so that buffered and compressed writers
+    /// can batch the output; call `flush` or `finish` once done.
+    pub fn write_tag(&mut self, tag: (&str, &Tag)) -> Result<()> {
+        self.write_named(tag)
+    }
+
+    /// Write a tag without a name to the stream, as sent over the network
+    /// since Minecraft 1.20.2: a type byte followed directly by the payload.
+    ///
+    /// `Tag::End` is written as a lone 0 byte, which the protocol uses for
+    /// an absent tag.
+    pub fn write_tag_unnamed(&mut self, tag: &Tag) -> Result<()> {
+        self.write_primitive(self.written_type(tag).to_binary() as i8)?;
+
+        if *tag == Tag::End {
+            return Ok(());
+        }
+
+        self.write_value(tag, true)
+    }
+
+    /// Flush any data buffered by the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    fn round(&self, x: f64) -> f64 {
+        match self.round_floats {
+            Some(d) => {
+                let scale = 10_f64.powi(d.min(i32::MAX as u32) as i32);
+                let scaled = x * scale;
+
+                // Values this large have no fractional digits left to round
+                if scale.is_finite() && scaled.is_finite() {
+                    scaled.round() / scale
+                } else {
+                    x
+                }
+            },
+            None => x
+        }
+    }
+
+    fn write_primitive<T: Encodable>(&mut self, i: T) -> Result<()> {
+        let mut bytes = i.to_bytes_nbt();
+
+        if self.endianness == Endianness::Little {
+            bytes.reverse();
+        }
+
+        Ok(self.writer.write_all(&bytes)?)
+    }
+
+    fn write_string(&mut self, s: &str) -> Result<()> {
+        let bytes = mutf8::encode(s);
+
+        if bytes.len() > i16::MAX as usize {
+            return Err(Error::Invalid);
+        }
+
+        self.write_primitive(bytes.len() as i16)?;
+
+        Ok(self.writer.write_all(&bytes)?)
+    }
+
+    // The array type a list is written as with `auto_compact_arrays`, if
+    // all of its elements are of the declared byte, int or long type.
+    fn compact_type(&self, tag: &Tag) -> Option<TagType> {
+        let x = match *tag {
+            Tag::List(ref x) if self.auto_compact_arrays => x,
+            _                                            => return None
+        };
+
+        let array = match x.element_type {
+            TagType::Byte => TagType::ByteArray,
+            TagType::Int  => TagType::IntArray,
+            TagType::Long => TagType::LongArray,
+            _             => return None
+        };
+
+        if x.elements.iter().all(|e| e.get_type() == x.element_type) {
+            Some(array)
+        } else {
+            None
+        }
+    }
+
+    fn written_type(&self, tag: &Tag) -> TagType {
+        self.compact_type(tag).unwrap_or_else(|| tag.get_type())
+    }
+
+    // `compact` is false for list elements that have to keep the list's
+    // declared type, because their siblings can't be compacted alike.
+    fn write_value(&mut self, tag: &Tag, compact: bool) -> Result<()> {
+        if let (true, Some(_), Tag::List(x)) = (compact, self.compact_type(tag), tag) {
+            self.write_primitive(x.elements.len() as i32)?;
 
             for i in x.elements.iter() {
-                write_value(writer, i)?;
+                match *i {
+                    Tag::Byte(b) => self.write_primitive(b)?,
+                    Tag::Int(n)  => self.write_primitive(n)?,
+                    Tag::Long(l) => self.write_primitive(l)?,
+                    _            => return Err(Error::Invalid)
+                }
             }
 
-        },
+            return Ok(());
+        }
 
-        Tag::Compound(ref x) => {
-            for (nam, val) in x.elements.iter() {
-                write_tag(writer, (nam, val))?;
-            }
+        match *tag {
+            Tag::End       => return Err(Error::Invalid),
+            Tag::Byte(x)   => self.write_primitive(x)?,
+            Tag::Short(x)  => self.write_primitive(x)?,
+            Tag::Int(x)    => self.write_primitive(x)?,
+            Tag::Long(x)   => self.write_primitive(x)?,
+            Tag::Float(x)  => {
+                let x = self.round(x as f64) as f32;
+                self.write_primitive(x)?
+            },
+            Tag::Double(x) => {
+                let x = self.round(x);
+                self.write_primitive(x)?
+            },
 
-            write_primitive(writer, 0_i8)?;
-        },
+            Tag::ByteArray(ref x) => {
+                self.write_primitive(x.len() as i32)?;
+                self.writer.write_all(x)?;
+            },
 
-        Tag::IntArray(ref x) => {
-            write_primitive(writer, x.len() as i32)?;
+            Tag::String(ref x) => self.write_string(x)?,
+            Tag::List(ref x) => {
+                let compacted = match x.elements.first() {
+                    Some(first) if x.element_type == TagType::List => {
+                        let t = self.compact_type(first);
+                        t.filter(|&t| x.elements.iter().all(|e| self.compact_type(e) == Some(t)))
+                    },
+                    _ => None
+                };
 
-            for i in x {
-                write_primitive(writer, *i)?;
-            }
-        },
+                let element_type = compacted.unwrap_or(x.element_type);
+
+                self.write_primitive(element_type.to_binary() as i8)?;
+                self.write_primitive(x.elements.len() as i32)?;
+
+                for i in x.elements.iter() {
+                    self.write_value(i, compacted.is_some())?;
+                }
+
+            },
+
+            Tag::Compound(ref x) => {
+                for (nam, val) in x.elements.iter() {
+                    self.write_named((nam, val))?;
+                }
+
+                self.write_primitive(0_i8)?;
+            },
+
+            Tag::IntArray(ref x) => {
+                self.write_primitive(x.len() as i32)?;
+
+                for i in x {
+                    self.write_primitive(*i)?;
+                }
+            },
 
-        Tag::LongArray(ref x) => {
-            write_primitive(writer, x.len() as i64)?;
+            Tag::LongArray(ref x) => {
+                self.write_primitive(x.len() as i32)?;
 
-            for i in x {
-                write_primitive(writer, *i)?;
+                for i in x {
+                    self.write_primitive(*i)?;
+                }
             }
+        };
+
+        Ok(())
+    }
+
+    fn write_named(&mut self, tag: (&str, &Tag)) -> Result<()> {
+        self.write_primitive(self.written_type(tag.1).to_binary() as i8)?;
+        self.write_string(tag.0)?;
+        self.write_value(tag.1, true)
+    }
+}
+
+#[test]
+fn test_round_floats() {
+    use std::io::Cursor;
+    use decode::Decoder;
+
+    let mut data = Vec::new();
+
+    {
+        let mut enc = Encoder::from_writer(&mut data);
+
+        enc.set_round_floats(Some(2));
+        enc.write_tag(("x", &Tag::Double(12.34567))).unwrap();
+        enc.write_tag(("y", &Tag::Float(-0.125))).unwrap();
+    }
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+
+    assert_eq!(dec.read_tag().unwrap(), ("x".to_owned(), Tag::Double(12.35)));
+    assert_eq!(dec.read_tag().unwrap(), ("y".to_owned(), Tag::Float(-0.13)));
+
+    let round = |decimals, x| {
+        let mut data = Vec::new();
+
+        {
+            let mut enc = Encoder::from_writer(&mut data);
+            enc.set_round_floats(Some(decimals));
+            enc.write_tag(("x", &Tag::Double(x))).unwrap();
+        }
+
+        match Decoder::from_reader(Cursor::new(data)).read_tag().unwrap().1 {
+            Tag::Double(x) => x,
+            _              => panic!("expected a double")
         }
     };
 
-    Ok(())
+    assert_eq!(round(2, 1e307), 1e307);
+    assert_eq!(round(400, 0.5), 0.5);
+    assert_eq!(round(u32::MAX, 0.5), 0.5);
 }
 
-fn write_tag<W: Write>(writer: &mut W, tag: (&str, &Tag)) -> Result<()> {
-    write_primitive(writer, tag.1.get_type().to_binary() as i8)?;
-    write_string(writer, tag.0)?;
-    write_value(writer, tag.1)?;
+#[test]
+fn test_unnamed_tag() {
+    use std::io::Cursor;
+    use super::CompoundData;
+    use decode::Decoder;
 
-    Ok(writer.flush()?)
+    let mut c = CompoundData::new();
+    c.insert("text".to_owned(), Tag::String("hi".to_owned()));
+    let root = Tag::Compound(c);
+
+    let mut data = Vec::new();
+    Encoder::from_writer(&mut data).write_tag_unnamed(&root).unwrap();
+
+    assert_eq!(data, [10, 8, 0, 4, b't', b'e', b'x', b't', 0, 2, b'h', b'i', 0]);
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    assert_eq!(dec.read_tag_unnamed().unwrap(), root);
+
+    let mut data = Vec::new();
+    Encoder::from_writer(&mut data).write_tag_unnamed(&Tag::End).unwrap();
+
+    assert_eq!(data, [0]);
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+    assert_eq!(dec.read_tag_unnamed().unwrap(), Tag::End);
 }
 
-/// Encode NBT tags.
-pub struct Encoder {
-    writer: Box<Write>
+#[test]
+fn test_finish() {
+    use decode::Decoder;
+    use util;
+
+    let tag = Tag::String("finished".to_owned());
+
+    let mut data = Vec::new();
+    let mut enc = Encoder::from_writer(GzEncoder::new(&mut data, flate2::Compression::default()));
+    enc.write_tag(("", &tag)).unwrap();
+    enc.finish().unwrap();
+
+    let mut dec = Decoder::from_reader_compressed(io::Cursor::new(data), Compression::GZip);
+    assert_eq!(dec.read_tag().unwrap(), ("".to_owned(), tag.clone()));
+
+    let dir = util::test_dir("finish");
+    let file = dir.join("finish.nbt");
+    let file = file.to_str().unwrap();
+
+    let mut enc = Encoder::from_file(file, Compression::Zlib).unwrap();
+    enc.write_tag(("", &tag)).unwrap();
+    enc.finish().unwrap();
+
+    assert_eq!(Decoder::from_file(file, Compression::Zlib).unwrap().read_tag().unwrap(),
+               ("".to_owned(), tag));
+
+    ::std::fs::remove_dir_all(&dir).unwrap();
 }
 
-// TODO: get rid of the box
-impl Encoder {
-    /// Create a new Encoder from an existing writer that will be taken
-    /// ownership over.
-    pub fn from_writer<W: Write + 'static>(writer: W) -> Encoder {
-        Encoder {
-            writer: Box::new(writer)
+#[test]
+fn test_gzip_stored() {
+    use std::fs;
+    use decode::Decoder;
+    use util;
+
+    let tag = Tag::ByteArray(vec![0; 4096]);
+    let dir = util::test_dir("gzip-stored");
+    let file = dir.join("gzip-stored.nbt");
+    let file = file.to_str().unwrap();
+
+    let mut enc = Encoder::from_file(file, Compression::GZipStored).unwrap();
+    enc.write_tag(("data", &tag)).unwrap();
+    enc.finish().unwrap();
+
+    let data = fs::read(file).unwrap();
+    let plain = 3 + 4 + tag.encoded_len();
+
+    assert_eq!(&data[.. 2], &[0x1F, 0x8B]);
+    assert!(data.len() > plain && data.len() < plain + 64);
+
+    let mut dec = Decoder::from_file(file, Compression::GZipStored).unwrap();
+    assert_eq!(dec.read_tag().unwrap(), ("data".to_owned(), tag));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_empty_list_type() {
+    use std::fs;
+    use decode::Decoder;
+    use types::{ListData, TagType};
+    use util;
+
+    let dir = util::test_dir("empty-list");
+    let file = dir.join("empty-list.nbt");
+    let file = file.to_str().unwrap();
+
+    let mut enc = Encoder::from_file(file, Compression::Uncompressed).unwrap();
+    enc.write_tag(("Items", &Tag::List(ListData::new(TagType::Compound)))).unwrap();
+    enc.finish().unwrap();
+
+    assert_eq!(fs::read(file).unwrap(),
+               vec![9, 0, 5, b'I', b't', b'e', b'm', b's', 10, 0, 0, 0, 0]);
+
+    let mut dec = Decoder::from_file(file, Compression::Uncompressed).unwrap();
+
+    match dec.read_tag().unwrap().1 {
+        Tag::List(ref l) => {
+            assert_eq!(l.element_type, TagType::Compound);
+            assert!(l.elements.is_empty());
+        },
+        _ => panic!("expected a list")
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_long_array() {
+    use std::fs;
+    use decode::Decoder;
+    use util;
+
+    let dir = util::test_dir("long-array");
+    let file = dir.join("long-array.nbt");
+    let file = file.to_str().unwrap();
+    let tag = Tag::LongArray(vec![1, -1, i64::MAX]);
+
+    let mut enc = Encoder::from_file(file, Compression::Uncompressed).unwrap();
+    enc.write_tag(("L", &tag)).unwrap();
+    enc.finish().unwrap();
+
+    let data = fs::read(file).unwrap();
+
+    assert_eq!(&data[.. 8], &[12, 0, 1, b'L', 0, 0, 0, 3]);
+    assert_eq!(data.len(), 4 + tag.encoded_len());
+
+    let mut dec = Decoder::from_file(file, Compression::Uncompressed).unwrap();
+    assert_eq!(dec.read_tag().unwrap(), ("L".to_owned(), tag));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_zlib() {
+    use std::fs;
+    use std::io::Cursor;
+    use decode::Decoder;
+    use util;
+
+    let dir = util::test_dir("zlib");
+    let file = dir.join("zlib.nbt");
+    let file = file.to_str().unwrap();
+    let tag = Tag::String("compressed".to_owned());
+
+    let mut enc = Encoder::from_file(file, Compression::Zlib).unwrap();
+    enc.write_tag(("s", &tag)).unwrap();
+    enc.finish().unwrap();
+
+    let data = fs::read(file).unwrap();
+    assert_eq!(data[0], 0x78);
+
+    let mut dec = Decoder::from_reader_compressed(Cursor::new(data), Compression::Zlib);
+    assert_eq!(dec.read_tag().unwrap(), ("s".to_owned(), tag.clone()));
+
+    assert_eq!(Decoder::read_file_auto(file).unwrap(),
+               (("s".to_owned(), tag), Compression::Zlib));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_little_endian() {
+    use std::io::Cursor;
+    use decode::Decoder;
+    use super::CompoundData;
+
+    let mut c = CompoundData::new();
+    c.insert("v".to_owned(), Tag::Int(0x01020304));
+
+    let mut data = Vec::new();
+    Encoder::from_writer_with(&mut data, Endianness::Little)
+        .write_tag(("", &Tag::Compound(c.clone()))).unwrap();
+
+    assert_eq!(data, vec![10, 0, 0, 3, 1, 0, b'v', 4, 3, 2, 1, 0]);
+
+    let mut dec = Decoder::from_reader_with(Cursor::new(data), Endianness::Little);
+    assert_eq!(dec.read_tag().unwrap(), ("".to_owned(), Tag::Compound(c)));
+}
+
+#[test]
+fn test_bytes_roundtrip() {
+    use decode;
+    use super::CompoundData;
+
+    let mut c = CompoundData::new();
+    c.insert("id".to_owned(), Tag::String("minecraft:stone".to_owned()));
+    c.insert("Count".to_owned(), Tag::Byte(64));
+    let tag = Tag::Compound(c);
+
+    let data = to_bytes("item", &tag).unwrap();
+
+    assert_eq!(data.len(), 3 + 4 + tag.encoded_len());
+    assert_eq!(decode::from_bytes(&data).unwrap(), ("item".to_owned(), tag));
+    assert!(decode::from_bytes(&data[.. data.len() - 1]).is_err());
+}
+
+#[test]
+fn test_named_root() {
+    use std::fs;
+    use decode::{self, Decoder};
+    use util;
+
+    // {"Schematic": {Width: 16s}}
+    let raw = vec![
+        10, 0, 9, b'S', b'c', b'h', b'e', b'm', b'a', b't', b'i', b'c',
+            2, 0, 5, b'W', b'i', b'd', b't', b'h', 0, 16,
+        0
+    ];
+
+    let dir = util::test_dir("named-root");
+    let file = dir.join("named-root.schematic");
+    let file = file.to_str().unwrap();
+    fs::write(file, &raw).unwrap();
+
+    let (name, tag) = Decoder::from_file(file, Compression::Uncompressed).unwrap().read_tag().unwrap();
+    assert_eq!(name, "Schematic");
+
+    let mut enc = Encoder::from_file(file, Compression::Uncompressed).unwrap();
+    enc.write_tag((&name, &tag)).unwrap();
+    enc.finish().unwrap();
+
+    assert_eq!(fs::read(file).unwrap(), raw);
+    assert_eq!(to_bytes(&name, &tag).unwrap(), raw);
+    assert_eq!(decode::from_bytes(&raw).unwrap(), (name, tag));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_modified_utf8_strings() {
+    use decode;
+
+    let text = "sign \u{1f600} with a \0 in it";
+    let data = to_bytes("", &Tag::String(text.to_owned())).unwrap();
+
+    assert_eq!(&data[3 .. 5], &[0, 27]);
+    assert_eq!(decode::from_bytes(&data).unwrap().1, Tag::String(text.to_owned()));
+
+    // Plain UTF-8 for the emoji is rejected instead of mangled
+    let mut bad = vec![8, 0, 0, 0, 4];
+    bad.extend("\u{1f600}".as_bytes());
+
+    match decode::from_bytes(&bad) {
+        Err(Error::Malformed) => (),
+        x                     => panic!("unexpected {:?}", x)
+    }
+}
+
+#[test]
+fn test_string_too_long() {
+    // Each NUL takes two bytes in modified UTF-8, so this is over the limit
+    // even though its plain UTF-8 length isn't.
+    let nuls = "\0".repeat(20000);
+
+    match to_bytes("", &Tag::String(nuls)) {
+        Err(Error::Invalid) => (),
+        x                   => panic!("unexpected {:?}", x)
+    }
+
+    let max = "a".repeat(i16::MAX as usize);
+    assert_eq!(to_bytes("", &Tag::String(max)).unwrap().len(), 3 + 2 + 32767);
+}
+
+#[test]
+fn test_buffered_writes() {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use super::CompoundData;
+
+    // Records the data and the number of writes
+    #[derive(Clone, Default)]
+    struct CountingWriter(Rc<RefCell<(Vec<u8>, usize)>>);
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut inner = self.0.borrow_mut();
+            inner.0.extend_from_slice(buf);
+            inner.1 += 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
     }
 
-    /// Create a new Encoder for the given file `file`, with the given
-    /// compression method.
-    pub fn from_file(file: &str, c: Compression) -> Result<Encoder> {
-        Ok(Encoder {
-            writer: match c {
-                Compression::Uncompressed =>
-                    Box::new(OpenOptions::new()
-                      .create(true)
-                      .truncate(true)
-                      .write(true)
-                      .open(file)?),
-
-                Compression::GZip =>
-                    Box::new(GzEncoder::new(
-                        OpenOptions::new()
-                          .create(true)
-                          .truncate(true)
-                          .write(true)
-                          .open(file)?,
-                        flate2::Compression::default()))
-            }
-        })
+    let mut comp = CompoundData::new();
+
+    for i in 0 .. 100 {
+        comp.insert(format!("k{}", i), Tag::Int(i));
     }
 
-    /// Write a named tag to the stream.
-    pub fn write_tag(&mut self, tag: (&str, &Tag)) -> Result<()> {
-        write_tag(&mut self.writer, tag)
+    let root = Tag::Compound(comp);
+    let (direct, buffered) = (CountingWriter::default(), CountingWriter::default());
+
+    Encoder::from_writer(direct.clone()).write_tag(("", &root)).unwrap();
+
+    let mut enc = Encoder::from_writer_buffered(buffered.clone(), Compression::Uncompressed);
+    enc.write_tag(("", &root)).unwrap();
+
+    // Everything is still held in the buffer...
+    assert!(buffered.0.borrow().0.is_empty());
+
+    // ...until the encoder is finished
+    enc.finish().unwrap();
+
+    let (direct, buffered) = (direct.0.borrow(), buffered.0.borrow());
+
+    assert_eq!(buffered.0, direct.0);
+    assert!(direct.1 >= 400);
+    assert!(buffered.1 * 2 < direct.1);
+}
+
+#[test]
+fn test_flush_only_on_request() {
+    use super::CompoundData;
+
+    struct FlushCounter<'a>(&'a mut usize);
+
+    impl<'a> Write for FlushCounter<'a> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            *self.0 += 1;
+            Ok(())
+        }
+    }
+
+    let mut comp = CompoundData::new();
+
+    for i in 0 .. 100 {
+        comp.insert(format!("k{}", i), Tag::Int(i));
+    }
+
+    let mut flushes = 0;
+
+    {
+        let mut enc = Encoder::from_writer(FlushCounter(&mut flushes));
+        enc.write_tag(("", &Tag::Compound(comp))).unwrap();
+        enc.write_tag_unnamed(&Tag::Int(1)).unwrap();
+        enc.flush().unwrap();
     }
+
+    assert_eq!(flushes, 1);
+}
+
+#[test]
+fn test_compression_roundtrip() {
+    use std::fs;
+    use decode::Decoder;
+    use util;
+
+    let tag = Tag::String("compressible ".repeat(100));
+
+    let dir = util::test_dir(&format!("compression-{}", util::flate_backend()));
+
+    for &c in &[Compression::GZip, Compression::GZipStored, Compression::Zlib] {
+        let file = dir.join(format!("{:?}.nbt", c));
+        let file = file.to_str().unwrap();
+
+        let mut enc = Encoder::from_file(file, c).unwrap();
+        enc.write_tag(("s", &tag)).unwrap();
+        enc.finish().unwrap();
+
+        let mut dec = Decoder::from_file(file, c).unwrap();
+        assert_eq!(dec.read_tag().unwrap(), ("s".to_owned(), tag.clone()));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_auto_compact_arrays() {
+    use std::io::Cursor;
+    use decode::Decoder;
+    use super::ListData;
+
+    let mut ints = ListData::new(TagType::Int);
+    ints.elements.extend(vec![Tag::Int(1), Tag::Int(-2), Tag::Int(3)]);
+
+    // Only some of the inner lists can be compacted, so none are
+    let mut mixed = ListData::new(TagType::List);
+    mixed.elements.push(Tag::List(ints.clone()));
+    mixed.elements.push(Tag::List(ListData::new(TagType::String)));
+
+    let mut nested = ListData::new(TagType::List);
+    nested.elements.push(Tag::List(ints.clone()));
+
+    let mut data = Vec::new();
+
+    {
+        let mut enc = Encoder::from_writer(&mut data);
+
+        enc.set_auto_compact_arrays(true);
+        enc.write_tag(("ints", &Tag::List(ints.clone()))).unwrap();
+        enc.write_tag(("mixed", &Tag::List(mixed.clone()))).unwrap();
+        enc.write_tag(("nested", &Tag::List(nested))).unwrap();
+    }
+
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+
+    let mut compacted = ListData::new(TagType::IntArray);
+    compacted.elements.push(Tag::IntArray(vec![1, -2, 3]));
+
+    assert_eq!(dec.read_tag().unwrap(), ("ints".to_owned(), Tag::IntArray(vec![1, -2, 3])));
+    assert_eq!(dec.read_tag().unwrap(), ("mixed".to_owned(), Tag::List(mixed)));
+    assert_eq!(dec.read_tag().unwrap(), ("nested".to_owned(), Tag::List(compacted)));
+
+    // Off by default
+    let data = to_bytes("ints", &Tag::List(ints.clone())).unwrap();
+    let mut dec = Decoder::from_reader(Cursor::new(data));
+
+    assert_eq!(dec.read_tag().unwrap(), ("ints".to_owned(), Tag::List(ints)));
+}
+
+#[test]
+fn test_tag_bytes_roundtrip() {
+    let tag = nbt!({ "Name": "Steve", "Pos": [0.0, 64.0, 0.0] });
+    let data = tag.to_bytes("Player").unwrap();
+
+    assert_eq!(data, to_bytes("Player", &tag).unwrap());
+    assert_eq!(Tag::from_bytes(&data).unwrap(), ("Player".to_owned(), tag));
+}
+
+#[test]
+fn test_short_writes() {
+    // Accepts at most one byte per call
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend(buf.iter().take(1));
+            Ok(buf.len().min(1))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let tag = nbt!({ "x": 1_000_000, "y": 2.5, "z": byte_array![1, 2] });
+    let mut enc = Encoder::from_writer(Trickle(Vec::new()));
+    enc.write_tag(("", &tag)).unwrap();
+
+    assert_eq!(enc.writer.0, to_bytes("", &tag).unwrap());
 }
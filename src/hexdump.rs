@@ -0,0 +1,152 @@
+//! Annotated hex dumps of encoded tags, for debugging the binary format.
+
+use super::{Tag, Encodable, Endianness};
+use mutf8;
+
+use std::fmt::Write;
+
+const BYTES_PER_LINE: usize = 16;
+
+struct Dumper {
+    out: String,
+    pos: usize,
+    endianness: Endianness
+}
+
+impl Dumper {
+    // One line per `BYTES_PER_LINE` bytes, with the note on the first one.
+    fn emit(&mut self, bytes: &[u8], note: &str) {
+        for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            let note = if i == 0 { note } else { "" };
+
+            let _ = writeln!(self.out, "{:08x}  {:<47}  {}", self.pos, hex.join(" "), note);
+            self.pos += chunk.len();
+        }
+    }
+
+    fn primitive<T: Encodable>(&mut self, x: T, note: &str) {
+        let mut bytes = x.to_bytes_nbt();
+
+        if self.endianness == Endianness::Little {
+            bytes.reverse();
+        }
+
+        self.emit(&bytes, note);
+    }
+
+    fn string(&mut self, s: &str, what: &str) {
+        let bytes = mutf8::encode(s);
+
+        self.primitive(bytes.len() as i16, &format!("{} length {}", what, bytes.len()));
+
+        if !bytes.is_empty() {
+            self.emit(&bytes, &format!("{} {:?}", what, s));
+        }
+    }
+
+    fn named(&mut self, name: &str, tag: &Tag) {
+        self.emit(&[tag.get_type().to_binary()], &format!("type {:?}", tag.get_type()));
+        self.string(name, "name");
+        self.payload(tag);
+    }
+
+    fn payload(&mut self, tag: &Tag) {
+        match *tag {
+            Tag::End       => (),
+            Tag::Byte(x)   => self.primitive(x, &format!("byte {}", x)),
+            Tag::Short(x)  => self.primitive(x, &format!("short {}", x)),
+            Tag::Int(x)    => self.primitive(x, &format!("int {}", x)),
+            Tag::Long(x)   => self.primitive(x, &format!("long {}", x)),
+            Tag::Float(x)  => self.primitive(x, &format!("float {:?}", x)),
+            Tag::Double(x) => self.primitive(x, &format!("double {:?}", x)),
+
+            Tag::String(ref x) => self.string(x, "string"),
+
+            Tag::ByteArray(ref x) => {
+                self.primitive(x.len() as i32, &format!("byte array length {}", x.len()));
+
+                if !x.is_empty() {
+                    self.emit(x, "bytes");
+                }
+            },
+
+            Tag::IntArray(ref x) => {
+                self.primitive(x.len() as i32, &format!("int array length {}", x.len()));
+
+                for &v in x {
+                    self.primitive(v, &format!("int {}", v));
+                }
+            },
+
+            Tag::LongArray(ref x) => {
+                self.primitive(x.len() as i32, &format!("long array length {}", x.len()));
+
+                for &v in x {
+                    self.primitive(v, &format!("long {}", v));
+                }
+            },
+
+            Tag::List(ref x) => {
+                let et = x.element_type;
+
+                self.emit(&[et.to_binary()], &format!("list element type {:?}", et));
+                self.primitive(x.elements.len() as i32, &format!("list length {}", x.elements.len()));
+
+                for e in x.elements.iter() {
+                    self.payload(e);
+                }
+            },
+
+            Tag::Compound(ref x) => {
+                for (n, v) in x.elements.iter() {
+                    self.named(n, v);
+                }
+
+                self.emit(&[0], "end");
+            }
+        }
+    }
+}
+
+impl Tag {
+    /// Return an annotated hex dump of the tag, encoded as a named tag with
+    /// the given byte order.
+    ///
+    /// Each line holds the offset, the bytes of one field of the encoding
+    /// and what they mean, e.g. `00000001  00 05  name length 5`. Fields
+    /// longer than 16 bytes continue on unannotated lines.
+    pub fn hex_dump(&self, name: &str, endianness: Endianness) -> String {
+        let mut d = Dumper { out: String::new(), pos: 0, endianness };
+
+        d.named(name, self);
+        d.out
+    }
+}
+
+#[test]
+fn test_hex_dump() {
+    use super::CompoundData;
+
+    let mut c = CompoundData::new();
+    c.insert("hp".to_owned(), Tag::Short(20));
+
+    let tag = Tag::Compound(c);
+    let big = tag.hex_dump("player", Endianness::Big);
+    let lines: Vec<&str> = big.lines().collect();
+
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines[0].split_whitespace().collect::<Vec<_>>(), ["00000000", "0a", "type", "Compound"]);
+    assert!(lines[2].starts_with("00000003  70 6c 61 79 65 72 "));
+    assert!(lines[2].ends_with("name \"player\""));
+    assert!(lines[6].starts_with("0000000e  00 14 "));
+    assert!(lines[6].ends_with("short 20"));
+    assert!(lines[7].starts_with("00000010  00 "));
+
+    let little = tag.hex_dump("player", Endianness::Little);
+    assert!(little.lines().nth(6).unwrap().starts_with("0000000e  14 00 "));
+
+    let long = Tag::ByteArray(vec![7; 20]).hex_dump("", Endianness::Big);
+    assert_eq!(long.lines().count(), 5);
+    assert!(long.lines().last().unwrap().starts_with("00000017  07 07 07 07 "));
+}
@@ -1,18 +1,45 @@
-#![feature(core_intrinsics)]
-#![feature(convert)]
-
 //! A a low level NBT decoding library that maps NBT structures onto
 //! standard library containers.
 
 extern crate flate2;
+#[cfg(feature = "derive")]
+extern crate nbt_derive;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "json")]
+extern crate serde_json;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_derive;
+
+#[macro_use]
+mod macros;
 
 pub mod types;
 pub mod decode;
 pub mod encode;
 pub mod util;
 pub mod traits;
+pub mod snbt;
+pub mod diff;
+pub mod normalize;
+pub mod schema;
+pub mod world;
+pub mod region;
+#[cfg(feature = "serde")]
+pub mod tag_serde;
+
+mod path;
+mod mutf8;
+mod yaml;
+mod hexdump;
 
 pub use types::*;
+pub use path::Segment;
+#[cfg(feature = "derive")]
+pub use nbt_derive::{ToNbt, FromNbt};
+#[cfg(feature = "serde")]
+pub use tag_serde::{to_tag, from_tag};
 
 // Trait for encoding values to bytes
 trait Encodable {
@@ -29,12 +56,12 @@ macro_rules! make_encodable {
         }
     };
 
-    // Encode a value by transmuting it to another value and using that impl
-    // i.e. transmute f32 to i32 -> i32.to_bytes()
+    // Encode a float by its bits as an integral of the same width
+    // i.e. f32 to bits as i32 -> i32.to_bytes()
     ($t:ty => $c:ty) => {
         impl Encodable for $t {
             fn to_bytes_nbt(&self) -> Vec<u8> {
-                unsafe { std::mem::transmute::<$t, $c>(*self) }.to_bytes_nbt()
+                (self.to_bits() as $c).to_bytes_nbt()
             }
         }
     };
@@ -52,6 +79,8 @@ make_encodable!(f64 => i64);
 macro_rules! make_decodable {
     ($t:ty, $s:expr, $($n:expr),+) => {
         impl Decodable for $t {
+            const SIZE: usize = $s;
+
             fn from_bytes_nbt(d: &[u8]) -> Option<Self> {
                 if d.len() != $s {
                     return None;
@@ -64,11 +93,10 @@ macro_rules! make_decodable {
 
     ($t:ty => $c:ty) => {
         impl Decodable for $t {
+            const SIZE: usize = <$c as Decodable>::SIZE;
+
             fn from_bytes_nbt(d: &[u8]) -> Option<Self> {
-                match <$c as Decodable>::from_bytes_nbt(d) {
-                    Some(x) => Some(unsafe { std::mem::transmute(x) }),
-                    None    => None
-                }
+                <$c as Decodable>::from_bytes_nbt(d).map(|x| <$t>::from_bits(x as _))
             }
         }
     };
@@ -76,6 +104,9 @@ macro_rules! make_decodable {
 
 // Trait for decoding values from bytes
 trait Decodable: Sized {
+    // Number of bytes the value occupies when encoded
+    const SIZE: usize;
+
     fn from_bytes_nbt(d: &[u8]) -> Option<Self>;
 }
 
@@ -88,7 +119,27 @@ make_decodable!(f32 => i32);
 make_decodable!(f64 => i64);
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn test_encode_decode() {
     assert_eq!(Some(0x1A2B_i16), i16::from_bytes_nbt(&(0x1A2B_i16).to_bytes_nbt()));
     assert_eq!(Some(-3.14_f32), f32::from_bytes_nbt(&(-3.14_f32).to_bytes_nbt()));
 }
+
+#[test]
+fn test_float_bits() {
+    let floats = [0x7FC0_0000, 0xFFC0_1234, 0x7F80_0001, 0x7F80_0000, 0xFF80_0000, 0x0000_0001, 0x8000_0000];
+
+    for &bits in &floats {
+        let x = f32::from_bits(bits);
+        assert_eq!(x.to_bytes_nbt(), (bits as i32).to_bytes_nbt());
+        assert_eq!(f32::from_bytes_nbt(&x.to_bytes_nbt()).unwrap().to_bits(), bits);
+    }
+
+    let doubles = [0x7FF8_0000_0000_0000, 0xFFF0_0000_0000_0BAD, 0x7FF0_0000_0000_0000, 0x0000_0000_0000_0001];
+
+    for &bits in &doubles {
+        let x = f64::from_bits(bits);
+        assert_eq!(x.to_bytes_nbt(), (bits as i64).to_bytes_nbt());
+        assert_eq!(f64::from_bytes_nbt(&x.to_bytes_nbt()).unwrap().to_bits(), bits);
+    }
+}
@@ -0,0 +1,198 @@
+//! Macros for building tags from literals.
+
+/// Create a `Tag::ByteArray` from a list of elements.
+///
+/// Elements may be signed or unsigned bytes, and are stored by their bit
+/// pattern: `byte_array![-1]` and `byte_array![255]` both hold 255.
+#[macro_export]
+macro_rules! byte_array {
+    ($($x:expr),* $(,)*) => {
+        $crate::Tag::ByteArray(vec![$($crate::traits::ByteArrayElement::to_byte($x)),*])
+    };
+}
+
+/// Create a `Tag::IntArray` from a list of elements.
+#[macro_export]
+macro_rules! int_array {
+    ($($x:expr),* $(,)*) => {
+        $crate::Tag::IntArray(vec![$($x),*])
+    };
+}
+
+/// Create a `Tag::LongArray` from a list of elements.
+#[macro_export]
+macro_rules! long_array {
+    ($($x:expr),* $(,)*) => {
+        $crate::Tag::LongArray(vec![$($x),*])
+    };
+}
+
+/// Create a tag from a JSON-like literal, like `serde_json::json!`.
+///
+/// `{ "key": value, ... }` creates a `Tag::Compound` and `[value, ...]` a
+/// `Tag::List`, whose element type is that of its first element (`End` if
+/// empty). Any other value is converted with `ToNbt`, so `"Steve"` becomes
+/// a `String`, `20.0f32` a `Float` and an untyped `20` an `Int`. Entries
+/// whose values convert to `Tag::End`, like `None`, are left out.
+///
+/// # Panics
+///
+/// Panics if the elements of a list have different types.
+#[macro_export]
+macro_rules! nbt {
+    (@compound $c:ident) => {};
+
+    (@compound $c:ident $k:tt : { $($v:tt)* } , $($rest:tt)*) => {
+        $crate::nbt!(@insert $c $k, $crate::nbt!({ $($v)* }));
+        $crate::nbt!(@compound $c $($rest)*);
+    };
+
+    (@compound $c:ident $k:tt : { $($v:tt)* }) => {
+        $crate::nbt!(@insert $c $k, $crate::nbt!({ $($v)* }));
+    };
+
+    (@compound $c:ident $k:tt : [ $($v:tt)* ] , $($rest:tt)*) => {
+        $crate::nbt!(@insert $c $k, $crate::nbt!([ $($v)* ]));
+        $crate::nbt!(@compound $c $($rest)*);
+    };
+
+    (@compound $c:ident $k:tt : [ $($v:tt)* ]) => {
+        $crate::nbt!(@insert $c $k, $crate::nbt!([ $($v)* ]));
+    };
+
+    (@compound $c:ident $k:tt : $v:expr , $($rest:tt)*) => {
+        $crate::nbt!(@insert $c $k, $crate::nbt!($v));
+        $crate::nbt!(@compound $c $($rest)*);
+    };
+
+    (@compound $c:ident $k:tt : $v:expr) => {
+        $crate::nbt!(@insert $c $k, $crate::nbt!($v));
+    };
+
+    (@insert $c:ident $k:expr, $v:expr) => {
+        match $v {
+            $crate::Tag::End => (),
+            t                => { $c.insert(::std::string::String::from($k), t); }
+        }
+    };
+
+    (@list $l:ident) => {};
+
+    (@list $l:ident { $($v:tt)* } , $($rest:tt)*) => {
+        $l.elements.push($crate::nbt!({ $($v)* }));
+        $crate::nbt!(@list $l $($rest)*);
+    };
+
+    (@list $l:ident { $($v:tt)* }) => {
+        $l.elements.push($crate::nbt!({ $($v)* }));
+    };
+
+    (@list $l:ident [ $($v:tt)* ] , $($rest:tt)*) => {
+        $l.elements.push($crate::nbt!([ $($v)* ]));
+        $crate::nbt!(@list $l $($rest)*);
+    };
+
+    (@list $l:ident [ $($v:tt)* ]) => {
+        $l.elements.push($crate::nbt!([ $($v)* ]));
+    };
+
+    (@list $l:ident $v:expr , $($rest:tt)*) => {
+        $l.elements.push($crate::nbt!($v));
+        $crate::nbt!(@list $l $($rest)*);
+    };
+
+    (@list $l:ident $v:expr) => {
+        $l.elements.push($crate::nbt!($v));
+    };
+
+    ({ $($tt:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut c = $crate::CompoundData::new();
+        $crate::nbt!(@compound c $($tt)*);
+        $crate::Tag::Compound(c)
+    }};
+
+    ([ $($tt:tt)* ]) => {{
+        #[allow(unused_mut)]
+        let mut l = $crate::ListData::new($crate::TagType::End);
+        $crate::nbt!(@list l $($tt)*);
+
+        if let Some(t) = l.elements.first().map($crate::Tag::get_type) {
+            l.element_type = t;
+        }
+
+        if l.elements.iter().any(|e| e.get_type() != l.element_type) {
+            panic!("nbt!: list elements have different tag types");
+        }
+
+        $crate::Tag::List(l)
+    }};
+
+    ($v:expr) => {
+        $crate::traits::ToNbt::to_nbt(&$v)
+    };
+}
+
+#[test]
+fn test_array_macros() {
+    use super::Tag;
+
+    assert_eq!(int_array![1, 2, 3], Tag::IntArray(vec![1, 2, 3]));
+    assert_eq!(long_array![1 << 40, -1,], Tag::LongArray(vec![1 << 40, -1]));
+    assert_eq!(byte_array![0, -128, 127], Tag::ByteArray(vec![0, 128, 127]));
+    assert_eq!(byte_array![128, 200, 255], Tag::ByteArray(vec![128, 200, 255]));
+    assert_eq!(byte_array![-1_i8, 200_u8], Tag::ByteArray(vec![255, 200]));
+    assert_eq!(int_array![], Tag::IntArray(vec![]));
+}
+
+#[test]
+fn test_nbt_macro() {
+    use super::{CompoundData, ListData, Tag, TagType};
+
+    let name = "Steve".to_owned();
+
+    let tag = nbt!({
+        "Name": name,
+        "Health": 20.0f32,
+        "Pos": [0.0, 64.0, 0.0],
+        "Score": -3,
+        "Items": [{ "id": "minecraft:stone", "Count": 1_i8 }, {}],
+        "Abilities": { "flying": false, },
+        "Ids": int_array![1, 2],
+        "Empty": [],
+        "Absent": None::<i32>
+    });
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.elements.extend(vec![Tag::Double(0.0), Tag::Double(64.0), Tag::Double(0.0)]);
+
+    let mut stone = CompoundData::new();
+    stone.insert("id".to_owned(), Tag::String("minecraft:stone".to_owned()));
+    stone.insert("Count".to_owned(), Tag::Byte(1));
+
+    let mut items = ListData::new(TagType::Compound);
+    items.elements.extend(vec![Tag::Compound(stone), Tag::Compound(CompoundData::new())]);
+
+    let mut abilities = CompoundData::new();
+    abilities.insert("flying".to_owned(), Tag::Byte(0));
+
+    let mut expected = CompoundData::new();
+    expected.insert("Name".to_owned(), Tag::String("Steve".to_owned()));
+    expected.insert("Health".to_owned(), Tag::Float(20.0));
+    expected.insert("Pos".to_owned(), Tag::List(pos));
+    expected.insert("Score".to_owned(), Tag::Int(-3));
+    expected.insert("Items".to_owned(), Tag::List(items));
+    expected.insert("Abilities".to_owned(), Tag::Compound(abilities));
+    expected.insert("Ids".to_owned(), Tag::IntArray(vec![1, 2]));
+    expected.insert("Empty".to_owned(), Tag::List(ListData::new(TagType::End)));
+
+    assert_eq!(tag, Tag::Compound(expected));
+    assert_eq!(nbt!([]), Tag::List(ListData::new(TagType::End)));
+    assert_eq!(nbt!("x"), Tag::String("x".to_owned()));
+}
+
+#[test]
+#[should_panic(expected = "list elements have different tag types")]
+fn test_nbt_macro_mixed_list() {
+    nbt!({ "l": [1, "a"] });
+}
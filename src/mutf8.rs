@@ -0,0 +1,99 @@
+//! Java's "modified UTF-8", the string encoding used by NBT.
+//!
+//! It differs from standard UTF-8 in encoding U+0000 as the two bytes
+//! `0xC0 0x80`, and characters outside the Basic Multilingual Plane as a
+//! surrogate pair of two three-byte sequences.
+
+/// Return the length of `s` in bytes when encoded as modified UTF-8.
+pub fn encoded_len(s: &str) -> usize {
+    s.chars().map(|c| match c as u32 {
+        0               => 2,
+        0x01..=0x7F     => 1,
+        0x80..=0x7FF    => 2,
+        0x800..=0xFFFF  => 3,
+        _               => 6
+    }).sum()
+}
+
+/// Encode `s` as modified UTF-8.
+pub fn encode(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(encoded_len(s));
+
+    // Working on UTF-16 code units splits supplementary characters into
+    // their surrogates, which are then encoded separately
+    for u in s.encode_utf16() {
+        match u {
+            0x01..=0x7F => out.push(u as u8),
+
+            0 | 0x80..=0x7FF => {
+                out.push(0xC0 | (u >> 6) as u8);
+                out.push(0x80 | (u & 0x3F) as u8);
+            },
+
+            _ => {
+                out.push(0xE0 | (u >> 12) as u8);
+                out.push(0x80 | ((u >> 6) & 0x3F) as u8);
+                out.push(0x80 | (u & 0x3F) as u8);
+            }
+        }
+    }
+
+    out
+}
+
+/// Decode modified UTF-8, or return `None` if `data` isn't valid.
+///
+/// Like Java's `DataInput::readUTF`, this accepts raw zero bytes and
+/// overlong two byte sequences, but not the four byte sequences of standard
+/// UTF-8 or unpaired surrogates.
+pub fn decode(data: &[u8]) -> Option<String> {
+    // Take the payload of a continuation byte
+    fn cont<I: Iterator<Item = u8>>(bytes: &mut I) -> Option<u16> {
+        match bytes.next() {
+            Some(b) if b & 0xC0 == 0x80 => Some((b & 0x3F) as u16),
+            _                           => None
+        }
+    }
+
+    let mut units = Vec::with_capacity(data.len());
+    let mut bytes = data.iter().cloned();
+
+    while let Some(b) = bytes.next() {
+        let b = b as u16;
+
+        units.push(match b {
+            0x00..=0x7F => b,
+            0xC0..=0xDF => (b & 0x1F) << 6 | cont(&mut bytes)?,
+            0xE0..=0xEF => (b & 0x0F) << 12 | cont(&mut bytes)? << 6 | cont(&mut bytes)?,
+            _           => return None
+        });
+    }
+
+    String::from_utf16(&units).ok()
+}
+
+#[test]
+fn test_modified_utf8() {
+    let cases: Vec<(&str, Vec<u8>)> = vec![
+        ("abc", vec![b'a', b'b', b'c']),
+        ("a\0b", vec![b'a', 0xC0, 0x80, b'b']),
+        ("\u{e9}", vec![0xC3, 0xA9]),
+        ("\u{20ac}", vec![0xE2, 0x82, 0xAC]),
+        ("\u{1f600}", vec![0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80])
+    ];
+
+    for (s, bytes) in cases {
+        assert_eq!(encode(s), bytes);
+        assert_eq!(encoded_len(s), bytes.len());
+        assert_eq!(decode(&bytes), Some(s.to_owned()));
+    }
+
+    // Standard UTF-8 for a supplementary character
+    assert_eq!(decode(&[0xF0, 0x9F, 0x98, 0x80]), None);
+    // An unpaired surrogate
+    assert_eq!(decode(&[0xED, 0xA0, 0xBD]), None);
+    // A truncated sequence
+    assert_eq!(decode(&[0xE2, 0x82]), None);
+    // Java accepts raw zero bytes
+    assert_eq!(decode(&[b'a', 0]), Some("a\0".to_owned()));
+}
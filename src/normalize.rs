@@ -0,0 +1,164 @@
+//! Bring tags into a canonical form for reproducible output.
+
+use super::{Tag, TagType};
+
+/// The transforms applied by `Tag::normalize`. All of them are off by
+/// default.
+///
+/// Compound keys have no order in a tag, so sorting them is left to the map
+/// type (see the `btreemap` feature) or to the output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Normalize {
+    /// Replace every NaN float or double with the canonical quiet NaN, so
+    /// that NaN payloads and signs don't leak into the output.
+    pub canonical_nan: bool,
+
+    /// Turn lists of bytes, ints and longs into the matching array tags.
+    pub compact_arrays: bool,
+
+    /// Sort the elements of every list by `Tag::total_cmp`.
+    pub sort_lists: bool,
+
+    /// Remove compound entries holding an empty compound, list or array.
+    /// Entries that become empty through pruning are removed as well. List
+    /// elements are never removed, since that would shift their indices.
+    pub prune_empty: bool
+}
+
+fn is_empty(t: &Tag) -> bool {
+    match *t {
+        Tag::ByteArray(ref x) => x.is_empty(),
+        Tag::IntArray(ref x)  => x.is_empty(),
+        Tag::LongArray(ref x) => x.is_empty(),
+        Tag::List(ref x)      => x.elements.is_empty(),
+        Tag::Compound(ref x)  => x.elements.is_empty(),
+        _                     => false
+    }
+}
+
+// Lists holding elements of another type than declared are left alone.
+fn compact(t: &mut Tag) {
+    let array = match *t {
+        Tag::List(ref x) => match x.element_type {
+            TagType::Byte => x.elements.iter()
+                .map(|e| match *e { Tag::Byte(b) => Some(b as u8), _ => None })
+                .collect::<Option<_>>().map(Tag::ByteArray),
+
+            TagType::Int => x.elements.iter()
+                .map(|e| match *e { Tag::Int(i) => Some(i), _ => None })
+                .collect::<Option<_>>().map(Tag::IntArray),
+
+            TagType::Long => x.elements.iter()
+                .map(|e| match *e { Tag::Long(l) => Some(l), _ => None })
+                .collect::<Option<_>>().map(Tag::LongArray),
+
+            _ => None
+        },
+        _ => None
+    };
+
+    if let Some(a) = array {
+        *t = a;
+    }
+}
+
+fn normalize(t: &mut Tag, opts: &Normalize) {
+    match *t {
+        Tag::List(ref mut x) => for e in x.elements.iter_mut() {
+            normalize(e, opts);
+        },
+        Tag::Compound(ref mut x) => for v in x.elements.values_mut() {
+            normalize(v, opts);
+        },
+        _ => ()
+    }
+
+    if opts.canonical_nan {
+        match *t {
+            Tag::Float(ref mut x) if x.is_nan()  => *x = f32::NAN,
+            Tag::Double(ref mut x) if x.is_nan() => *x = f64::NAN,
+            _                                    => ()
+        }
+    }
+
+    if opts.compact_arrays {
+        compact(t);
+    }
+
+    if opts.sort_lists {
+        if let Tag::List(ref mut x) = *t {
+            x.elements.sort_by(|a, b| a.total_cmp(b));
+        }
+    }
+
+    if opts.prune_empty {
+        if let Tag::Compound(ref mut x) = *t {
+            let empty: Vec<String> = x.elements.iter()
+                .filter(|&(_, v)| is_empty(v))
+                .map(|(k, _)| k.clone())
+                .collect();
+
+            for k in empty {
+                x.elements.remove(&k);
+            }
+        }
+    }
+}
+
+impl Tag {
+    /// Apply the transforms selected in `opts` to the whole tree.
+    ///
+    /// Children are normalized before their parent. On each tag, NaNs are
+    /// canonicalized first, then lists compacted to arrays, then lists
+    /// sorted, and finally empty entries pruned.
+    pub fn normalize(&mut self, opts: &Normalize) {
+        normalize(self, opts);
+    }
+}
+
+#[test]
+fn test_normalize() {
+    use super::{CompoundData, ListData};
+
+    let mut ids = ListData::new(TagType::Int);
+    ids.elements.extend(vec![Tag::Int(3), Tag::Int(1)]);
+
+    let mut names = ListData::new(TagType::String);
+    names.elements.extend(vec![Tag::String("b".to_owned()), Tag::String("a".to_owned())]);
+
+    let mut inner = CompoundData::new();
+    inner.insert("Items".to_owned(), Tag::List(ListData::new(TagType::Compound)));
+
+    let mut root = CompoundData::new();
+    root.insert("Ids".to_owned(), Tag::List(ids));
+    root.insert("Names".to_owned(), Tag::List(names));
+    root.insert("Inner".to_owned(), Tag::Compound(inner));
+    root.insert("Health".to_owned(), Tag::Float(f32::from_bits(0xFFC0_1234)));
+
+    let mut root = Tag::Compound(root);
+
+    root.normalize(&Normalize {
+        canonical_nan: true,
+        compact_arrays: true,
+        sort_lists: true,
+        prune_empty: true
+    });
+
+    let c = match root {
+        Tag::Compound(ref c) => c,
+        _                    => panic!("expected a compound")
+    };
+
+    let mut sorted = ListData::new(TagType::String);
+    sorted.elements.extend(vec![Tag::String("a".to_owned()), Tag::String("b".to_owned())]);
+
+    // Compacted before sorting, so the array keeps its order
+    assert_eq!(c.elements.get("Ids"), Some(&Tag::IntArray(vec![3, 1])));
+    assert_eq!(c.elements.get("Names"), Some(&Tag::List(sorted)));
+    assert_eq!(c.elements.get("Inner"), None);
+
+    match c.elements.get("Health") {
+        Some(&Tag::Float(x)) => assert_eq!(x.to_bits(), f32::NAN.to_bits()),
+        _                    => panic!("expected a float")
+    }
+}
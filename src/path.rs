@@ -0,0 +1,817 @@
+//! Paths addressing tags nested inside a tree.
+//!
+//! A path is a sequence of compound keys separated by `.`, with list
+//! elements addressed by `[n]`, e.g. `Level.Entities[3].id`. The empty path
+//! refers to the root tag itself.
+
+use super::{Error, Result, Tag, TagType, ListData, CompoundData};
+
+/// A single step in a path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    /// A compound key.
+    Key(String),
+    /// A list index.
+    Index(usize)
+}
+
+/// Render a sequence of segments as a path string.
+pub fn format(segments: &[Segment]) -> String {
+    let mut p = String::new();
+
+    for s in segments {
+        p = match *s {
+            Segment::Key(ref k) => join_key(&p, k),
+            Segment::Index(i)   => join_index(&p, i)
+        };
+    }
+
+    p
+}
+
+/// Append a compound key to `parent`.
+///
+/// The key isn't escaped, so the result can't be parsed back into the same
+/// segments if it contains `.` or `[`.
+pub fn join_key(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// Append a list index to `parent`.
+pub fn join_index(parent: &str, i: usize) -> String {
+    format!("{}[{}]", parent, i)
+}
+
+/// Parse a path string into its segments, or `None` if it's malformed.
+pub fn parse(p: &str) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+
+    if p.is_empty() {
+        return Some(segments);
+    }
+
+    for (n, part) in p.split('.').enumerate() {
+        let (key, mut rest) = match part.find('[') {
+            Some(i) => (&part[.. i], &part[i ..]),
+            None    => (part, "")
+        };
+
+        // Only a root list may be indexed without a leading key
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_owned()));
+        } else if n > 0 || rest.is_empty() {
+            return None;
+        }
+
+        while !rest.is_empty() {
+            if !rest.starts_with('[') {
+                return None;
+            }
+
+            let close = rest.find(']')?;
+            let i = rest[1 .. close].parse().ok()?;
+
+            segments.push(Segment::Index(i));
+            rest = &rest[close + 1 ..];
+        }
+    }
+
+    Some(segments)
+}
+
+fn child<'a>(tag: &'a Tag, segment: &Segment) -> Option<&'a Tag> {
+    match (tag, segment) {
+        (Tag::Compound(c), Segment::Key(k)) => c.elements.get(k),
+        (Tag::List(l), Segment::Index(i))   => l.elements.get(*i),
+        _                                   => None
+    }
+}
+
+fn child_mut<'a>(tag: &'a mut Tag, segment: &Segment) -> Option<&'a mut Tag> {
+    match (tag, segment) {
+        (Tag::Compound(c), Segment::Key(k)) => c.elements.get_mut(k),
+        (Tag::List(l), Segment::Index(i))   => l.elements.get_mut(*i),
+        _                                   => None
+    }
+}
+
+/// Look up the tag at `segments` below `tag`.
+pub fn get<'a>(tag: &'a Tag, segments: &[Segment]) -> Option<&'a Tag> {
+    segments.iter().try_fold(tag, |t, s| child(t, s))
+}
+
+/// Look up the tag at `segments` below `tag` for modification.
+pub fn get_mut<'a>(tag: &'a mut Tag, segments: &[Segment]) -> Option<&'a mut Tag> {
+    let mut t = tag;
+
+    for s in segments {
+        t = child_mut(t, s)?;
+    }
+
+    Some(t)
+}
+
+/// Remove and return the tag at `segments` below `tag`.
+pub fn remove(tag: &mut Tag, segments: &[Segment]) -> Option<Tag> {
+    let (last, parent) = segments.split_last()?;
+
+    match (get_mut(tag, parent)?, last) {
+        (&mut Tag::Compound(ref mut c), Segment::Key(k)) => c.elements.remove(k),
+
+        (&mut Tag::List(ref mut l), &Segment::Index(i)) if i < l.elements.len() =>
+            Some(l.elements.remove(i)),
+
+        _ => None
+    }
+}
+
+// Whether `insert` would succeed, checked without modifying `tag`.
+fn can_insert(tag: &Tag, parent: &[Segment], last: &Segment, value: &Tag, create: bool) -> bool {
+    let mut t = tag;
+
+    for (n, s) in parent.iter().enumerate() {
+        t = match child(t, s) {
+            Some(t) => t,
+
+            // The rest of the path has to be created, which only works for
+            // keys below a compound
+            None => return create
+                && t.get_type() == TagType::Compound
+                && parent[n ..].iter().chain(Some(last)).all(|s| match *s {
+                    Segment::Key(_)   => true,
+                    Segment::Index(_) => false
+                })
+        };
+    }
+
+    match (t, last) {
+        (&Tag::Compound(_), &Segment::Key(_)) => true,
+
+        (Tag::List(l), Segment::Index(i)) =>
+            *i <= l.elements.len()
+                && (l.elements.is_empty() || value.get_type() == l.element_type),
+
+        _ => false
+    }
+}
+
+// Put `value` back at `segments` right after `remove` took it from there,
+// even into a list whose elements don't match its type.
+fn restore(tag: &mut Tag, segments: &[Segment], value: Tag) -> bool {
+    let (last, parent) = match segments.split_last() {
+        Some(x) => x,
+        None    => return false
+    };
+
+    match (get_mut(tag, parent), last) {
+        (Some(&mut Tag::Compound(ref mut c)), Segment::Key(k)) => {
+            c.elements.insert(k.clone(), value);
+            true
+        },
+
+        (Some(&mut Tag::List(ref mut l)), &Segment::Index(i)) if i <= l.elements.len() => {
+            l.elements.insert(i, value);
+            true
+        },
+
+        _ => false
+    }
+}
+
+/// Insert `value` at `segments` below `tag`, replacing an existing compound
+/// entry or shifting later list elements back.
+///
+/// Missing compounds along the way are created if `create` is set. Returns
+/// the value back if it can't be inserted, in which case `tag` is left
+/// unchanged.
+pub fn insert(tag: &mut Tag, segments: &[Segment], value: Tag, create: bool)
+        -> ::std::result::Result<(), Tag> {
+
+    let (last, parent) = match segments.split_last() {
+        Some(x) => x,
+        None    => return Err(value)
+    };
+
+    if !can_insert(tag, parent, last, &value, create) {
+        return Err(value);
+    }
+
+    let mut t = tag;
+
+    for s in parent {
+        if create {
+            if let (&mut Tag::Compound(ref mut c), Segment::Key(k)) = (&mut *t, s) {
+                if !c.elements.contains_key(k) {
+                    c.elements.insert(k.clone(),
+                        Tag::Compound(CompoundData::new()));
+                }
+            }
+        }
+
+        t = match child_mut(t, s) {
+            Some(t) => t,
+            None    => return Err(value)
+        };
+    }
+
+    match (t, last) {
+        (&mut Tag::Compound(ref mut c), Segment::Key(k)) => {
+            c.elements.insert(k.clone(), value);
+            Ok(())
+        },
+
+        (&mut Tag::List(ref mut l), &Segment::Index(i)) => {
+            if l.elements.is_empty() {
+                l.element_type = value.get_type();
+            }
+
+            l.elements.insert(i, value);
+            Ok(())
+        },
+
+        _ => Err(value)
+    }
+}
+
+// Copy the parts of `tag` addressed by `paths`, or `None` if none of them
+// lead anywhere.
+fn project(tag: &Tag, paths: &[&[Segment]]) -> Option<Tag> {
+    if paths.iter().any(|p| p.is_empty()) {
+        return Some(tag.clone());
+    }
+
+    // Group the paths by their first step, keeping the order of first use
+    let mut steps: Vec<(&Segment, Vec<&[Segment]>)> = Vec::new();
+
+    for p in paths {
+        let (first, rest) = p.split_first().unwrap();
+
+        match steps.iter().position(|&(s, _)| s == first) {
+            Some(i) => steps[i].1.push(rest),
+            None    => steps.push((first, vec![rest]))
+        }
+    }
+
+    match *tag {
+        Tag::Compound(_) => {
+            let mut out = CompoundData::new();
+
+            for (s, rest) in steps {
+                if let (Some(v), Segment::Key(k)) = (child(tag, s), s) {
+                    if let Some(v) = project(v, &rest) {
+                        out.insert(k.clone(), v);
+                    }
+                }
+            }
+
+            if out.is_empty() { None } else { Some(Tag::Compound(out)) }
+        },
+
+        Tag::List(ref l) => {
+            let mut out = ListData::new(l.element_type);
+            steps.sort_by_key(|&(s, _)| match *s { Segment::Index(i) => i, _ => 0 });
+
+            for (s, rest) in steps {
+                if let Some(v) = child(tag, s).and_then(|v| project(v, &rest)) {
+                    out.elements.push(v);
+                }
+            }
+
+            if out.elements.is_empty() { None } else { Some(Tag::List(out)) }
+        },
+
+        _ => None
+    }
+}
+
+// Whether `tag` is a number, a list of numbers or a numeric array.
+fn is_numeric(tag: &Tag) -> bool {
+    match *tag {
+        Tag::ByteArray(_) | Tag::IntArray(_) | Tag::LongArray(_) => true,
+        Tag::List(ref l) => l.element_type.fixed_size().is_some(),
+        _                => tag.get_type().fixed_size().is_some()
+    }
+}
+
+// Apply `f` to a number, or to every element of a list of numbers or a
+// numeric array.
+fn transform_numbers<F: Fn(f64) -> f64>(tag: &mut Tag, f: &F) {
+    match *tag {
+        Tag::Byte(ref mut x)      => *x = f(*x as f64) as i8,
+        Tag::Short(ref mut x)     => *x = f(*x as f64) as i16,
+        Tag::Int(ref mut x)       => *x = f(*x as f64) as i32,
+        Tag::Long(ref mut x)      => *x = f(*x as f64) as i64,
+        Tag::Float(ref mut x)     => *x = f(*x as f64) as f32,
+        Tag::Double(ref mut x)    => *x = f(*x),
+        Tag::ByteArray(ref mut x) => for v in x { *v = f(*v as i8 as f64) as i8 as u8 },
+        Tag::IntArray(ref mut x)  => for v in x { *v = f(*v as f64) as i32 },
+        Tag::LongArray(ref mut x) => for v in x { *v = f(*v as f64) as i64 },
+        Tag::List(ref mut l)      => for v in l.elements.iter_mut() { transform_numbers(v, f) },
+        _                         => ()
+    }
+}
+
+fn find_first<'a, F: Fn(&str, &Tag) -> bool>(tag: &'a Tag, p: &str, pred: &F) -> Option<(String, &'a Tag)> {
+    if pred(p, tag) {
+        return Some((p.to_owned(), tag));
+    }
+
+    match *tag {
+        Tag::List(ref x) => x.elements.iter().enumerate()
+            .filter_map(|(i, e)| find_first(e, &join_index(p, i), pred))
+            .next(),
+
+        Tag::Compound(ref x) => x.elements.iter()
+            .filter_map(|(k, v)| find_first(v, &join_key(p, k), pred))
+            .next(),
+
+        _ => None
+    }
+}
+
+fn extract_all<F: Fn(&str, &Tag) -> bool>(tag: &mut Tag, p: &str, pred: &F, out: &mut Vec<(String, Tag)>) {
+    match *tag {
+        Tag::List(ref mut x) => {
+            let mut kept = Vec::with_capacity(x.elements.len());
+
+            for (i, mut e) in x.elements.drain(..).enumerate() {
+                let child = join_index(p, i);
+
+                if pred(&child, &e) {
+                    out.push((child, e));
+                } else {
+                    extract_all(&mut e, &child, pred, out);
+                    kept.push(e);
+                }
+            }
+
+            x.elements = kept;
+        },
+
+        Tag::Compound(ref mut x) => {
+            let matched: Vec<String> = x.elements.iter()
+                .filter(|&(k, v)| pred(&join_key(p, k), v))
+                .map(|(k, _)| k.clone())
+                .collect();
+
+            for k in matched {
+                let v = x.elements.remove(&k).unwrap();
+                out.push((join_key(p, &k), v));
+            }
+
+            for (k, v) in x.elements.iter_mut() {
+                extract_all(v, &join_key(p, k), pred, out);
+            }
+        },
+
+        _ => ()
+    }
+}
+
+impl Tag {
+    /// Look up the tag at `path`, e.g. `Data.Player.Pos[1]`.
+    ///
+    /// Returns `None` if there's nothing there, including when a step along
+    /// the way has the wrong type. Use `get_path_segments` for keys that
+    /// contain `.` or `[`.
+    pub fn get_path(&self, path: &str) -> Option<&Tag> {
+        get(self, &parse(path)?)
+    }
+
+    /// Look up the tag at `path` for modification, like `get_path`.
+    pub fn get_path_mut(&mut self, path: &str) -> Option<&mut Tag> {
+        get_mut(self, &parse(path)?)
+    }
+
+    /// Look up the tag at the path made of `segments`.
+    pub fn get_path_segments(&self, segments: &[Segment]) -> Option<&Tag> {
+        get(self, segments)
+    }
+
+    /// Look up the tag at the path made of `segments` for modification.
+    pub fn get_path_segments_mut(&mut self, segments: &[Segment]) -> Option<&mut Tag> {
+        get_mut(self, segments)
+    }
+
+    /// Return a deep copy of the subtree at `path`, or `None` if there's
+    /// nothing there.
+    pub fn clone_path(&self, path: &str) -> Option<Tag> {
+        get(self, &parse(path)?).cloned()
+    }
+
+    /// Return a compound holding only the subtrees at `keep_paths`, nested
+    /// the same way they are in this tag.
+    ///
+    /// Lists along the way keep only the addressed elements, in their
+    /// original order. Paths that are malformed or lead nowhere are ignored.
+    pub fn project(&self, keep_paths: &[&str]) -> Tag {
+        let parsed: Vec<Vec<Segment>> = keep_paths.iter().filter_map(|p| parse(p)).collect();
+        let paths: Vec<&[Segment]> = parsed.iter().map(|p| &p[..]).collect();
+
+        match project(self, &paths) {
+            Some(t @ Tag::Compound(_)) => t,
+            _                          => Tag::Compound(CompoundData::new())
+        }
+    }
+
+    /// Sort the elements of the lists at `paths` by `Tag::total_cmp`, for
+    /// lists whose order doesn't matter.
+    ///
+    /// Fails with `Error::PathNotFound` without sorting anything if one of
+    /// the paths doesn't lead to a list.
+    pub fn sort_lists(&mut self, paths: &[&str]) -> Result<()> {
+        let mut parsed = Vec::new();
+
+        for p in paths {
+            let segments = parse(p);
+
+            match segments.as_ref().and_then(|s| get(self, s)) {
+                Some(&Tag::List(_)) => parsed.push(segments.unwrap()),
+                _                   => return Err(Error::PathNotFound((*p).to_owned()))
+            }
+        }
+
+        for s in parsed {
+            if let Some(&mut Tag::List(ref mut l)) = get_mut(self, &s) {
+                l.elements.sort_by(|a, b| a.total_cmp(b));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the numbers at `paths` with the result of applying `f` to
+    /// them.
+    ///
+    /// A path may lead to a single number, a list of numbers or a numeric
+    /// array, whose elements are all transformed. Results are converted back
+    /// to the original type with `as`, so integers are truncated towards zero
+    /// and saturate at their type's bounds.
+    ///
+    /// Fails with `Error::PathNotFound` without changing anything if one of
+    /// the paths doesn't lead to a number.
+    pub fn transform_numbers<F: Fn(f64) -> f64>(&mut self, paths: &[&str], f: F) -> Result<()> {
+        let mut parsed = Vec::new();
+
+        for p in paths {
+            let segments = parse(p);
+
+            match segments.as_ref().and_then(|s| get(self, s)) {
+                Some(t) if is_numeric(t) => parsed.push(segments.unwrap()),
+                _                        => return Err(Error::PathNotFound((*p).to_owned()))
+            }
+        }
+
+        for s in parsed {
+            if let Some(t) = get_mut(self, &s) {
+                transform_numbers(t, &f);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the path and value of the first tag in the tree, in depth-first
+    /// order starting with the tag itself, for which `pred` returns true.
+    ///
+    /// The search stops at the first match. Compound entries are visited in
+    /// the compound's iteration order.
+    ///
+    /// Keys are joined into the path without escaping, so the path is lossy
+    /// for keys containing `.` or `[` and `get_path` won't find them again.
+    /// Look those up with `get_path_segments` instead.
+    pub fn find_first<F: Fn(&str, &Tag) -> bool>(&self, pred: F) -> Option<(String, &Tag)> {
+        find_first(self, "", &pred)
+    }
+
+    /// Remove every subtree for which `pred` returns true and return them
+    /// with the paths they were found at.
+    ///
+    /// Matching subtrees aren't searched further, and the tag itself is never
+    /// removed. Paths of list elements are their indices before any elements
+    /// were removed. As with `find_first`, paths are lossy for keys
+    /// containing `.` or `[`.
+    pub fn extract_all<F: Fn(&str, &Tag) -> bool>(&mut self, pred: F) -> Vec<(String, Tag)> {
+        let mut out = Vec::new();
+        extract_all(self, "", &pred, &mut out);
+
+        out
+    }
+
+    /// Move the subtree at path `from` to path `to`.
+    ///
+    /// Missing compounds leading up to `to` are created if `create` is set,
+    /// otherwise they cause an error. The move either happens completely or
+    /// not at all: if the subtree can't be inserted at `to`, it is put back
+    /// where it was.
+    pub fn move_path(&mut self, from: &str, to: &str, create: bool) -> Result<()> {
+        let src = parse(from).ok_or_else(|| Error::PathNotFound(from.to_owned()))?;
+        let dst = parse(to).ok_or_else(|| Error::PathNotFound(to.to_owned()))?;
+
+        let value = remove(self, &src).ok_or_else(|| Error::PathNotFound(from.to_owned()))?;
+
+        match insert(self, &dst, value, create) {
+            Ok(()) => Ok(()),
+
+            // A failed insert leaves the tree as it was after the removal
+            Err(value) => if restore(self, &src, value) {
+                Err(Error::PathNotFound(to.to_owned()))
+            } else {
+                Err(Error::Invalid)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse() {
+    let k = |s: &str| Segment::Key(s.to_owned());
+
+    assert_eq!(parse(""), Some(vec![]));
+    assert_eq!(parse("a.b[2][0].c"),
+               Some(vec![k("a"), k("b"), Segment::Index(2), Segment::Index(0), k("c")]));
+    assert_eq!(parse("[1].x"), Some(vec![Segment::Index(1), k("x")]));
+    assert_eq!(parse("a..b"), None);
+    assert_eq!(parse("a[x]"), None);
+    assert_eq!(parse("a[1"), None);
+}
+
+#[test]
+fn test_move_path() {
+    let mut pos = CompoundData::new();
+    pos.insert("x".to_owned(), Tag::Int(1));
+
+    let mut player = CompoundData::new();
+    player.insert("Pos".to_owned(), Tag::Compound(pos));
+
+    let mut root = CompoundData::new();
+    root.insert("Player".to_owned(), Tag::Compound(player));
+
+    let mut root = Tag::Compound(root);
+
+    assert!(root.move_path("Player.Pos", "Data.Spawn", false).is_err());
+    assert!(get_mut(&mut root, &parse("Player.Pos").unwrap()).is_some());
+
+    root.move_path("Player.Pos", "Data.Spawn", true).unwrap();
+
+    assert!(get_mut(&mut root, &parse("Player.Pos").unwrap()).is_none());
+    assert_eq!(get_mut(&mut root, &parse("Data.Spawn.x").unwrap()), Some(&mut Tag::Int(1)));
+    assert!(root.move_path("Player.Pos", "Foo", true).is_err());
+
+    // Failed moves leave no trace of the attempt
+    let mut tag = nbt!({ "a": 1, "l": [], "m": [1, 2] });
+
+    if let Tag::List(ref mut l) = tag["m"] {
+        l.elements.push(Tag::String("odd".to_owned()));
+    }
+
+    let before = tag.clone();
+
+    assert!(tag.move_path("a", "x.y.l2[3]", true).is_err());
+    assert!(tag.move_path("a", "l[3]", false).is_err());
+    assert!(tag.move_path("m[2]", "m[5]", false).is_err());
+    assert_eq!(tag, before);
+
+    tag.move_path("a", "l[0]", false).unwrap();
+    assert_eq!(tag["l"], nbt!([1]));
+}
+
+#[test]
+fn test_clone_path() {
+    let mut pos = CompoundData::new();
+    pos.insert("x".to_owned(), Tag::Int(1));
+
+    let mut root = CompoundData::new();
+    root.insert("Pos".to_owned(), Tag::Compound(pos));
+    let root = Tag::Compound(root);
+
+    let mut copy = root.clone_path("Pos").unwrap();
+
+    if let Tag::Compound(ref mut c) = copy {
+        c.insert("x".to_owned(), Tag::Int(2));
+    }
+
+    assert_eq!(root.clone_path("Pos.x"), Some(Tag::Int(1)));
+    assert_eq!(copy.clone_path("x"), Some(Tag::Int(2)));
+    assert_eq!(root.clone_path("Pos.y"), None);
+    assert_eq!(root.clone_path("Pos[0]"), None);
+}
+
+#[test]
+fn test_project() {
+    use super::TagType;
+
+    let mut item = CompoundData::new();
+    item.insert("id".to_owned(), Tag::String("stone".to_owned()));
+    item.insert("Count".to_owned(), Tag::Byte(3));
+
+    let mut items = ListData::new(TagType::Compound);
+    items.elements.push(Tag::Compound(CompoundData::new()));
+    items.elements.push(Tag::Compound(item));
+
+    let mut player = CompoundData::new();
+    player.insert("Name".to_owned(), Tag::String("Steve".to_owned()));
+    player.insert("Health".to_owned(), Tag::Float(20.0));
+    player.insert("Inventory".to_owned(), Tag::List(items));
+
+    let mut root = CompoundData::new();
+    root.insert("Player".to_owned(), Tag::Compound(player));
+    root.insert("Seed".to_owned(), Tag::Long(42));
+    let root = Tag::Compound(root);
+
+    let p = root.project(&["Player.Health", "Player.Inventory[1].id", "Missing.key", "a..b"]);
+
+    assert_eq!(p.count_nodes(), 6);
+    assert_eq!(p.clone_path("Player.Health"), Some(Tag::Float(20.0)));
+    assert_eq!(p.clone_path("Player.Inventory[0].id"), Some(Tag::String("stone".to_owned())));
+    assert_eq!(p.clone_path("Player.Inventory[0].Count"), None);
+    assert_eq!(p.clone_path("Player.Name"), None);
+    assert_eq!(p.clone_path("Seed"), None);
+
+    assert_eq!(root.project(&[""]), root);
+    assert_eq!(root.project(&[]), Tag::Compound(CompoundData::new()));
+}
+
+#[test]
+fn test_sort_lists() {
+    use super::TagType;
+
+    let mut tags = ListData::new(TagType::String);
+
+    for t in &["zombie", "creeper", "skeleton", "creeper"] {
+        tags.elements.push(Tag::String((*t).to_owned()));
+    }
+
+    let mut entity = CompoundData::new();
+    entity.insert("Tags".to_owned(), Tag::List(tags));
+    entity.insert("id".to_owned(), Tag::Int(1));
+
+    let mut root = CompoundData::new();
+    root.insert("Entity".to_owned(), Tag::Compound(entity));
+    let mut root = Tag::Compound(root);
+    let before = root.clone();
+
+    assert!(root.sort_lists(&["Entity.Tags", "Entity.id"]).is_err());
+    assert_eq!(root, before);
+
+    root.sort_lists(&["Entity.Tags"]).unwrap();
+
+    let sorted: Vec<_> = ["creeper", "creeper", "skeleton", "zombie"].iter()
+        .map(|t| Tag::String((*t).to_owned()))
+        .collect();
+
+    match root.clone_path("Entity.Tags") {
+        Some(Tag::List(l)) => assert_eq!(l.elements, sorted),
+        _                  => panic!("expected a list")
+    }
+
+    assert_eq!(Tag::Byte(5).total_cmp(&Tag::Int(1)), ::std::cmp::Ordering::Less);
+    assert_eq!(Tag::Double(-0.0).total_cmp(&Tag::Double(0.0)), ::std::cmp::Ordering::Less);
+}
+
+#[test]
+fn test_transform_numbers() {
+    use super::TagType;
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.extend(vec![Tag::Double(1.5), Tag::Double(-3.0), Tag::Double(64.0)]);
+
+    let mut root = CompoundData::new();
+    root.insert("Pos".to_owned(), Tag::List(pos));
+    root.insert("xPos".to_owned(), Tag::Int(-7));
+    root.insert("Count".to_owned(), Tag::Byte(100));
+    root.insert("Name".to_owned(), Tag::String("x".to_owned()));
+    let mut root = Tag::Compound(root);
+    let before = root.clone();
+
+    assert!(root.transform_numbers(&["Pos", "Name"], |x| x * 2.0).is_err());
+    assert!(root.transform_numbers(&["Pos", "Missing"], |x| x * 2.0).is_err());
+    assert_eq!(root, before);
+
+    root.transform_numbers(&["Pos", "xPos", "Count"], |x| x * 2.0).unwrap();
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.extend(vec![Tag::Double(3.0), Tag::Double(-6.0), Tag::Double(128.0)]);
+
+    assert_eq!(root.clone_path("Pos"), Some(Tag::List(pos)));
+    assert_eq!(root.clone_path("xPos"), Some(Tag::Int(-14)));
+    assert_eq!(root.clone_path("Count"), Some(Tag::Byte(127)));
+}
+
+#[test]
+fn test_get_path() {
+    use super::TagType;
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.extend(vec![Tag::Double(1.0), Tag::Double(64.0)]);
+
+    let mut player = CompoundData::new();
+    player.insert("Pos".to_owned(), Tag::List(pos));
+    player.insert("minecraft:stat.jump".to_owned(), Tag::Int(3));
+
+    let mut data = CompoundData::new();
+    data.insert("Player".to_owned(), Tag::Compound(player));
+
+    let mut root = CompoundData::new();
+    root.insert("Data".to_owned(), Tag::Compound(data));
+    let mut root = Tag::Compound(root);
+
+    assert_eq!(root.get_path("Data.Player.Pos[1]"), Some(&Tag::Double(64.0)));
+    assert_eq!(root.get_path("Data.Player.Pos[2]"), None);
+    assert_eq!(root.get_path("Data.Player[0]"), None);
+    assert_eq!(root.get_path("Data.Player.Pos.x"), None);
+    assert_eq!(root.get_path("Data..Player"), None);
+    assert_eq!(root.get_path("Data.Player.minecraft:stat.jump"), None);
+
+    let k = |s: &str| Segment::Key(s.to_owned());
+    let jump = [k("Data"), k("Player"), k("minecraft:stat.jump")];
+
+    assert_eq!(root.get_path_segments(&jump), Some(&Tag::Int(3)));
+
+    *root.get_path_mut("Data.Player.Pos[0]").unwrap() = Tag::Double(2.0);
+    *root.get_path_segments_mut(&jump).unwrap() = Tag::Int(4);
+
+    assert_eq!(root.get_path("Data.Player.Pos[0]"), Some(&Tag::Double(2.0)));
+    assert_eq!(root.get_path_segments(&jump), Some(&Tag::Int(4)));
+}
+
+#[test]
+fn test_find_first() {
+    use std::cell::Cell;
+    use super::TagType;
+
+    let mut ent = CompoundData::new();
+    ent.insert("id".to_owned(), Tag::String("minecraft:cow".to_owned()));
+
+    let mut root = CompoundData::new();
+    root.insert("Entities".to_owned(), Tag::List(ListData {
+        element_type: TagType::Compound,
+        elements: vec![Tag::Compound(ent.clone()), Tag::Compound(ent)]
+    }));
+
+    let root = Tag::Compound(root);
+    let seen = Cell::new(0);
+
+    let found = root.find_first(|_, t| {
+        if let Tag::String(_) = *t {
+            seen.set(seen.get() + 1);
+            assert_eq!(seen.get(), 1, "visited a string after the first match");
+            true
+        } else {
+            false
+        }
+    });
+
+    assert_eq!(found, Some(("Entities[0].id".to_owned(), &Tag::String("minecraft:cow".to_owned()))));
+    assert_eq!(root.find_first(|p, _| p.is_empty()), Some(("".to_owned(), &root)));
+    assert_eq!(root.find_first(|_, t| *t == Tag::Int(0)), None);
+
+    // Dotted keys make the path lossy
+    let mut c = CompoundData::new();
+    c.insert("a.b".to_owned(), Tag::Int(1));
+    let root = Tag::Compound(c);
+
+    let (p, _) = root.find_first(|_, t| *t == Tag::Int(1)).unwrap();
+    assert_eq!(p, "a.b");
+    assert_eq!(root.get_path(&p), None);
+    assert_eq!(root.get_path_segments(&[Segment::Key("a.b".to_owned())]), Some(&Tag::Int(1)));
+}
+
+#[test]
+fn test_extract_all() {
+    use super::TagType;
+
+    let mob = |id: &str| {
+        let mut c = CompoundData::new();
+        c.insert("id".to_owned(), Tag::String(id.to_owned()));
+        Tag::Compound(c)
+    };
+
+    let mut ents = ListData::new(TagType::Compound);
+    ents.elements.extend(vec![mob("minecraft:zombie"), mob("minecraft:cow"), mob("minecraft:zombie")]);
+
+    let mut root = CompoundData::new();
+    root.insert("Entities".to_owned(), Tag::List(ents));
+    root.insert("Boss".to_owned(), mob("minecraft:zombie"));
+
+    let mut root = Tag::Compound(root);
+    let zombie = Tag::String("minecraft:zombie".to_owned());
+
+    let mut out = root.extract_all(|_, t| match *t {
+        Tag::Compound(ref c) => c.elements.get("id") == Some(&zombie),
+        _                    => false
+    });
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let paths: Vec<&str> = out.iter().map(|e| &e.0[..]).collect();
+    assert_eq!(paths, ["Boss", "Entities[0]", "Entities[2]"]);
+    assert_eq!(out[1].1, mob("minecraft:zombie"));
+
+    assert_eq!(root.get_path("Boss"), None);
+    assert_eq!(root.get_path("Entities[0].id"), Some(&Tag::String("minecraft:cow".to_owned())));
+    assert_eq!(root.get_path("Entities[1]"), None);
+}
@@ -0,0 +1,252 @@
+//! Read chunks from Anvil region (`.mca`) files.
+//!
+//! A region file holds the chunks of a 32 by 32 chunk area. It starts with
+//! a 4 KiB table of chunk locations and a 4 KiB table of timestamps, followed
+//! by the chunks in 4 KiB sectors, each prefixed by its length and
+//! compression scheme.
+
+use super::{Error, Result, Tag, Compression};
+use decode::Decoder;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use flate2::bufread::{GzDecoder, ZlibDecoder};
+
+const SECTOR: u64 = 4096;
+
+/// A region file, read from `R`.
+pub struct RegionFile<R> {
+    reader: R,
+    locations: Vec<u32>,
+    timestamps: Vec<u32>
+}
+
+fn read_u32s(data: &[u8]) -> Vec<u32> {
+    data.chunks(4)
+        .map(|b| (b[0] as u32) << 24 | (b[1] as u32) << 16 | (b[2] as u32) << 8 | b[3] as u32)
+        .collect()
+}
+
+// Map the compression scheme byte of a chunk to a compression method.
+fn chunk_compression(scheme: u8) -> Result<Compression> {
+    match scheme {
+        1 => Ok(Compression::GZip),
+        2 => Ok(Compression::Zlib),
+        3 => Ok(Compression::Uncompressed),
+        _ => Err(Error::Malformed)
+    }
+}
+
+// Decode the one tag in `reader`, failing if any data follows it.
+fn read_exactly_one<D: Read>(reader: &mut D) -> Result<Tag> {
+    let tag = Decoder::from_reader(&mut *reader).read_tag()?.1;
+
+    if reader.read(&mut [0_u8; 1])? != 0 {
+        return Err(Error::Malformed);
+    }
+
+    Ok(tag)
+}
+
+// Decode a chunk payload, which has to be used up exactly by its tag.
+fn decode_chunk(payload: &[u8], c: Compression) -> Result<Tag> {
+    let (tag, rest) = match c {
+        Compression::GZip => {
+            let mut d = GzDecoder::new(payload);
+            (read_exactly_one(&mut d)?, d.into_inner())
+        },
+
+        Compression::Zlib => {
+            let mut d = ZlibDecoder::new(payload);
+            (read_exactly_one(&mut d)?, d.into_inner())
+        },
+
+        _ => {
+            let mut d = payload;
+            (read_exactly_one(&mut d)?, d)
+        }
+    };
+
+    if !rest.is_empty() {
+        return Err(Error::Malformed);
+    }
+
+    Ok(tag)
+}
+
+impl RegionFile<File> {
+    /// Open the region file at `path`.
+    pub fn open(path: &str) -> Result<RegionFile<File>> {
+        RegionFile::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> RegionFile<R> {
+    /// Read the headers of a region file from `reader`.
+    ///
+    /// Fails with `Error::Malformed` if the headers are incomplete.
+    pub fn new(mut reader: R) -> Result<RegionFile<R>> {
+        let mut header = vec![0; 2 * SECTOR as usize];
+
+        reader.seek(SeekFrom::Start(0))?;
+
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(Error::Malformed),
+            Err(e) => return Err(Error::from(e))
+        }
+
+        Ok(RegionFile {
+            reader,
+            locations: read_u32s(&header[.. SECTOR as usize]),
+            timestamps: read_u32s(&header[SECTOR as usize ..])
+        })
+    }
+
+    fn slot(x: u8, z: u8) -> Result<usize> {
+        if x < 32 && z < 32 {
+            Ok(z as usize * 32 + x as usize)
+        } else {
+            Err(Error::Invalid)
+        }
+    }
+
+    /// Return whether the chunk at local coordinates `(x, z)` is present.
+    /// Coordinates outside of `0 .. 32` are never present.
+    pub fn has_chunk(&self, x: u8, z: u8) -> bool {
+        Self::slot(x, z).is_ok_and(|s| self.locations[s] != 0)
+    }
+
+    /// Return the last modification time of the chunk at local coordinates
+    /// `(x, z)`, in seconds since the epoch, if it's present.
+    pub fn timestamp(&self, x: u8, z: u8) -> Option<u32> {
+        if self.has_chunk(x, z) {
+            Some(self.timestamps[z as usize * 32 + x as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Read and decode the chunk at local coordinates `(x, z)`, which must
+    /// be less than 32. Returns `None` if the chunk isn't present.
+    ///
+    /// Fails with `Error::Malformed` if the chunk's length doesn't fit the
+    /// sectors allocated to it, if it uses an unsupported compression
+    /// scheme, or if its tag doesn't use up exactly the declared length.
+    pub fn chunk(&mut self, x: u8, z: u8) -> Result<Option<Tag>> {
+        let loc = self.locations[Self::slot(x, z)?];
+
+        if loc == 0 {
+            return Ok(None);
+        }
+
+        let (offset, sectors) = ((loc >> 8) as u64, (loc & 0xFF) as u64);
+        let mut header = [0_u8; 5];
+
+        self.reader.seek(SeekFrom::Start(offset * SECTOR))?;
+        self.read_exact(&mut header)?;
+
+        let len = read_u32s(&header[.. 4])[0] as u64;
+
+        if len == 0 || len + 4 > sectors * SECTOR {
+            return Err(Error::Malformed);
+        }
+
+        let c = chunk_compression(header[4])?;
+        let mut payload = vec![0; len as usize - 1];
+        self.read_exact(&mut payload)?;
+
+        decode_chunk(&payload, c).map(Some)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(()),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(Error::UnexpectedEof),
+            Err(e) => Err(Error::from(e))
+        }
+    }
+}
+
+// Build a region file holding the given chunks, as `(x, z, payload)` with
+// the compression byte leading the payload.
+#[cfg(test)]
+pub fn build_region(chunks: &[(u8, u8, Vec<u8>)]) -> Vec<u8> {
+    let mut region = vec![0_u8; 2 * SECTOR as usize];
+
+    for &(x, z, ref payload) in chunks {
+        let sector = region.len() / SECTOR as usize;
+        let count = (4 + payload.len()).div_ceil(SECTOR as usize);
+        let slot = z as usize * 32 + x as usize;
+
+        region[slot * 4 .. slot * 4 + 4].copy_from_slice(
+            &[(sector >> 16) as u8, (sector >> 8) as u8, sector as u8, count as u8]);
+        region[SECTOR as usize + slot * 4 + 3] = 42;
+
+        let len = payload.len() as u32;
+        region.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+        region.extend_from_slice(payload);
+
+        let padded = (sector + count) * SECTOR as usize;
+        region.resize(padded, 0);
+    }
+
+    region
+}
+
+#[test]
+fn test_region_file() {
+    use std::io::{Cursor, Write};
+    use flate2;
+    use flate2::write::ZlibEncoder;
+
+    // {"": {xPos: 2}}
+    let raw = vec![10, 0, 0, 3, 0, 4, b'x', b'P', b'o', b's', 0, 0, 0, 2, 0];
+
+    let mut z = ZlibEncoder::new(vec![2], flate2::Compression::default());
+    z.write_all(&raw).unwrap();
+
+    let mut plain = vec![3];
+    plain.extend(&raw);
+
+    let zlib = z.finish().unwrap();
+
+    // Declared lengths covering more than the tag
+    let mut plain_long = plain.clone();
+    plain_long.extend(&[0, 0, 0]);
+
+    let mut zlib_long = zlib.clone();
+    zlib_long.extend(&[1, 2]);
+
+    let data = build_region(&[(2, 3, zlib), (31, 31, plain), (0, 0, vec![9, 0]),
+                              (5, 5, plain_long), (6, 6, zlib_long)]);
+    let mut region = RegionFile::new(Cursor::new(data)).unwrap();
+
+    for &(x, z) in &[(2, 3), (31, 31)] {
+        let chunk = region.chunk(x, z).unwrap().unwrap();
+        assert_eq!(chunk.get_path("xPos"), Some(&Tag::Int(2)));
+        assert_eq!(region.timestamp(x, z), Some(42));
+    }
+
+    assert!(region.chunk(1, 1).unwrap().is_none());
+    assert_eq!(region.timestamp(1, 1), None);
+    assert!(!region.has_chunk(32, 0));
+
+    for &(x, z) in &[(0, 0), (5, 5), (6, 6)] {
+        match region.chunk(x, z) {
+            Err(Error::Malformed) => (),
+            r                     => panic!("unexpected {:?}", r)
+        }
+    }
+
+    match region.chunk(32, 0) {
+        Err(Error::Invalid) => (),
+        x                   => panic!("unexpected {:?}", x)
+    }
+
+    match RegionFile::new(Cursor::new(vec![0; 100])) {
+        Err(Error::Malformed) => (),
+        _                     => panic!("expected a malformed header")
+    }
+}
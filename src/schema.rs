@@ -0,0 +1,137 @@
+//! Describe the expected structure of compounds and check tags against it.
+
+use super::{Error, Result, Tag, TagType, CompoundData};
+use path;
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+enum Field {
+    Value(TagType),
+    Compound(Schema)
+}
+
+/// The allowed keys of a compound and the types of their values.
+///
+/// Every key is optional, but keys not in the schema aren't allowed.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    fields: HashMap<String, Field>
+}
+
+impl Schema {
+    /// Create a schema that allows no keys.
+    pub fn new() -> Schema {
+        Schema { fields: HashMap::new() }
+    }
+
+    /// Allow the key `name` with values of type `t`.
+    pub fn field(mut self, name: &str, t: TagType) -> Schema {
+        self.fields.insert(name.to_owned(), Field::Value(t));
+        self
+    }
+
+    /// Allow the key `name` with a compound value conforming to `schema`.
+    pub fn compound(mut self, name: &str, schema: Schema) -> Schema {
+        self.fields.insert(name.to_owned(), Field::Compound(schema));
+        self
+    }
+
+    /// Check that `tag` is a compound conforming to the schema, failing with
+    /// `Error::SchemaViolation` and the path of the first offending value if
+    /// it isn't.
+    pub fn validate(&self, tag: &Tag) -> Result<()> {
+        self.validate_at(tag, "")
+    }
+
+    fn validate_at(&self, tag: &Tag, p: &str) -> Result<()> {
+        match *tag {
+            Tag::Compound(ref c) => {
+                for (k, v) in c.elements.iter() {
+                    self.check(k, v, &path::join_key(p, k))?;
+                }
+
+                Ok(())
+            },
+
+            _ => Err(Error::SchemaViolation(p.to_owned()))
+        }
+    }
+
+    fn check(&self, key: &str, value: &Tag, p: &str) -> Result<()> {
+        match self.fields.get(key) {
+            Some(&Field::Value(t)) if value.get_type() == t => Ok(()),
+            Some(Field::Compound(s))                        => s.validate_at(value, p),
+            _ => Err(Error::SchemaViolation(p.to_owned()))
+        }
+    }
+}
+
+/// Build a compound that is guaranteed to conform to a `Schema`.
+///
+/// Each entry is checked against the schema as it's set, so mistakes show up
+/// where they're made instead of in a later validation pass.
+pub struct SchemaBuilder<'a> {
+    schema: &'a Schema,
+    data: CompoundData
+}
+
+impl<'a> SchemaBuilder<'a> {
+    /// Create a builder for an empty compound conforming to `schema`.
+    pub fn new(schema: &'a Schema) -> SchemaBuilder<'a> {
+        SchemaBuilder {
+            schema,
+            data: CompoundData::new()
+        }
+    }
+
+    /// Set the entry `key` to `value`, failing with `Error::SchemaViolation`
+    /// if the schema doesn't allow it.
+    pub fn set(&mut self, key: &str, value: Tag) -> Result<&mut SchemaBuilder<'a>> {
+        self.schema.check(key, &value, key)?;
+        self.data.insert(key.to_owned(), value);
+
+        Ok(self)
+    }
+
+    /// Finish building, returning the compound.
+    pub fn build(self) -> Tag {
+        Tag::Compound(self.data)
+    }
+}
+
+#[test]
+fn test_schema_builder() {
+    let schema = Schema::new()
+        .field("Name", TagType::String)
+        .field("Health", TagType::Float)
+        .compound("Pos", Schema::new().field("x", TagType::Int));
+
+    let mut pos = CompoundData::new();
+    pos.insert("x".to_owned(), Tag::Int(3));
+
+    let mut b = SchemaBuilder::new(&schema);
+    b.set("Name", Tag::String("Steve".to_owned())).unwrap()
+     .set("Health", Tag::Float(20.0)).unwrap();
+    b.set("Pos", Tag::Compound(pos)).unwrap();
+
+    let tag = b.build();
+    assert!(schema.validate(&tag).is_ok());
+
+    let mut b = SchemaBuilder::new(&schema);
+
+    match b.set("Hunger", Tag::Int(20)) {
+        Err(Error::SchemaViolation(p)) => assert_eq!(p, "Hunger"),
+        _                              => panic!("expected a schema violation")
+    }
+
+    assert!(b.set("Health", Tag::Int(20)).is_err());
+
+    let mut pos = CompoundData::new();
+    pos.insert("y".to_owned(), Tag::Int(3));
+
+    match b.set("Pos", Tag::Compound(pos)) {
+        Err(Error::SchemaViolation(p)) => assert_eq!(p, "Pos.y"),
+        _                              => panic!("expected a schema violation")
+    }
+}
@@ -0,0 +1,839 @@
+//! Work with SNBT, the stringified NBT syntax used by commands and datapacks.
+
+use super::{Error, Result, Tag, TagType, ListData, CompoundData};
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// A byte range in the SNBT source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+/// A single SNBT token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// `{`
+    LBrace,
+    /// `}`
+    RBrace,
+    /// `[`
+    LBracket,
+    /// `]`
+    RBracket,
+    /// `:`
+    Colon,
+    /// `;`
+    Semicolon,
+    /// `,`
+    Comma,
+    /// A quoted string, with escape sequences already resolved.
+    String(String),
+    /// A numeric literal and its lowercased type suffix, if any.
+    Number { value: String, suffix: Option<char> },
+    /// Any other unquoted run of characters, such as a key or `true`.
+    Word(String)
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' || c == '+'
+}
+
+// Split an unquoted word into a number and its suffix, if it is one.
+fn classify_word(word: &str) -> Token {
+    let (body, suffix) = match word.chars().last() {
+        Some(c) if "bBsSlLfFdD".contains(c) => {
+            (&word[.. word.len() - 1], Some(c.to_ascii_lowercase()))
+        },
+        _ => (word, None)
+    };
+
+    let digits = body.trim_start_matches(['-', '+']);
+    let signs = body.len() - digits.len();
+
+    let mut seen_digit = false;
+    let mut seen_dot = false;
+    let mut seen_exp = false;
+    let mut prev = ' ';
+
+    let numeric = signs <= 1 && digits.chars().all(|c| {
+        let ok = match c {
+            '0'..='9' => { seen_digit = true; true },
+            '.' if !seen_dot && !seen_exp => { seen_dot = true; true },
+            'e' | 'E' if seen_digit && !seen_exp => { seen_exp = true; true },
+            '-' | '+' => prev == 'e' || prev == 'E',
+            _ => false
+        };
+
+        prev = c;
+        ok
+    }) && seen_digit && !prev.is_ascii_alphabetic() && prev != '-' && prev != '+';
+
+    if numeric {
+        Token::Number { value: body.to_owned(), suffix }
+    } else {
+        Token::Word(word.to_owned())
+    }
+}
+
+/// Split SNBT source text into tokens, independent of parsing.
+///
+/// The lexer yields each token along with its byte span and stops after the
+/// first error, so it can be used on incomplete input as well.
+pub struct Lexer<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    failed: bool
+}
+
+impl<'a> Lexer<'a> {
+    /// Create a new lexer over `src`.
+    pub fn new(src: &'a str) -> Lexer<'a> {
+        Lexer {
+            src,
+            chars: src.char_indices().peekable(),
+            failed: false
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+
+            self.chars.next();
+        }
+    }
+
+    fn quoted(&mut self, start: usize, quote: char) -> Result<(Token, Span)> {
+        let mut s = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some((i, c)) if c == quote =>
+                    return Ok((Token::String(s), Span { start, end: i + 1 })),
+
+                Some((i, '\\')) => match self.escape() {
+                    Some(c) => s.push(c),
+                    None    => return Err(Error::Syntax {
+                        offset: i,
+                        message: "invalid escape sequence".to_owned()
+                    })
+                },
+
+                Some((_, c)) => s.push(c),
+
+                None => return Err(Error::Syntax {
+                    offset: start,
+                    message: "unterminated string".to_owned()
+                })
+            }
+        }
+    }
+
+    // Resolve the escape sequence following a backslash.
+    fn escape(&mut self) -> Option<char> {
+        Some(match self.chars.next()?.1 {
+            c @ '\\' | c @ '"' | c @ '\'' => c,
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            's' => ' ',
+
+            'u' => {
+                let mut code = 0;
+
+                for _ in 0 .. 4 {
+                    code = code * 16 + self.chars.next()?.1.to_digit(16)?;
+                }
+
+                ::std::char::from_u32(code)?
+            },
+
+            _ => return None
+        })
+    }
+
+    fn unquoted(&mut self, start: usize) -> (Token, Span) {
+        let mut end = start;
+
+        while let Some(&(i, c)) = self.chars.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+
+            end = i + c.len_utf8();
+            self.chars.next();
+        }
+
+        (classify_word(&self.src[start .. end]), Span { start, end })
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<(Token, Span)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        self.skip_whitespace();
+
+        let (start, c) = match self.chars.peek() {
+            Some(&x) => x,
+            None     => return None
+        };
+
+        let single = match c {
+            '{' => Some(Token::LBrace),
+            '}' => Some(Token::RBrace),
+            '[' => Some(Token::LBracket),
+            ']' => Some(Token::RBracket),
+            ':' => Some(Token::Colon),
+            ';' => Some(Token::Semicolon),
+            ',' => Some(Token::Comma),
+            _   => None
+        };
+
+        if let Some(t) = single {
+            self.chars.next();
+            return Some(Ok((t, Span { start, end: start + 1 })));
+        }
+
+        let res = if c == '"' || c == '\'' {
+            self.chars.next();
+            self.quoted(start, c)
+        } else if is_unquoted_char(c) {
+            Ok(self.unquoted(start))
+        } else {
+            Err(Error::Syntax {
+                offset: start,
+                message: format!("unexpected character '{}'", c)
+            })
+        };
+
+        self.failed = res.is_err();
+        Some(res)
+    }
+}
+
+fn syntax_error<T>(offset: usize, message: &str) -> Result<T> {
+    Err(Error::Syntax {
+        offset,
+        message: message.to_owned()
+    })
+}
+
+// How deeply compounds and lists may nest, like the default of
+// `Decoder::set_max_depth`, so that hostile input can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+// What follows the `[` of a list, as found by `Parser::list_start`.
+enum ListStart {
+    Empty,
+    Array(Tag),
+    Element(usize, Tag),
+    Values
+}
+
+// Parse a value that isn't a compound or list.
+fn scalar(t: Token, span: Span) -> Result<Tag> {
+    match t {
+        Token::String(s) => Ok(Tag::String(s)),
+
+        Token::Number { value, suffix } => number(&value, suffix)
+            .ok_or_else(|| Error::Syntax {
+                offset: span.start,
+                message: "number out of range".to_owned()
+            }),
+
+        Token::Word(w) => Ok(match &w[..] {
+            "true"  => Tag::Byte(1),
+            "false" => Tag::Byte(0),
+            _       => Tag::String(w)
+        }),
+
+        _ => syntax_error(span.start, "expected a value")
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    lexer: Peekable<Lexer<'a>>,
+    depth: usize
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<(Token, Span)> {
+        match self.lexer.next() {
+            Some(t) => t,
+            None    => syntax_error(self.src.len(), "unexpected end of input")
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<Token>> {
+        match self.lexer.peek() {
+            Some(&Ok((ref t, _))) => return Ok(Some(t.clone())),
+            Some(&Err(_))         => (),
+            None                  => return Ok(None)
+        }
+
+        self.next().map(|_| None)
+    }
+
+    fn expect(&mut self, expected: Token, message: &str) -> Result<()> {
+        let (t, span) = self.next()?;
+
+        if t == expected {
+            Ok(())
+        } else {
+            syntax_error(span.start, message)
+        }
+    }
+
+    fn value(&mut self) -> Result<Tag> {
+        let (t, span) = self.next()?;
+
+        let compound = match t {
+            Token::LBrace   => true,
+            Token::LBracket => false,
+            t               => return scalar(t, span)
+        };
+
+        if self.depth >= MAX_DEPTH {
+            return syntax_error(span.start, "nesting too deep");
+        }
+
+        self.depth += 1;
+        let v = if compound { self.compound() } else { self.list() };
+        self.depth -= 1;
+
+        v
+    }
+
+    // Whether the next token is `close`, consuming it if so.
+    fn close(&mut self, close: Token) -> Result<bool> {
+        if self.peek()? == Some(close) {
+            self.next()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    // Parse the `,` or `close` after an element, with an optional trailing
+    // comma. Returns whether the container ended.
+    fn separator(&mut self, close: Token, message: &str) -> Result<bool> {
+        let (t, span) = self.next()?;
+
+        if t == Token::Comma {
+            self.close(close)
+        } else if t == close {
+            Ok(true)
+        } else {
+            syntax_error(span.start, message)
+        }
+    }
+
+    // Parse a compound key and the `:` following it.
+    fn key(&mut self) -> Result<String> {
+        let (t, span) = self.next()?;
+
+        let key = match t {
+            Token::String(s)                      => s,
+            Token::Word(_) | Token::Number { .. } => self.src[span.start .. span.end].to_owned(),
+            _ => return syntax_error(span.start, "expected a key")
+        };
+
+        self.expect(Token::Colon, "expected ':'")?;
+        Ok(key)
+    }
+
+    // Recursion goes through `value`, `compound` and `list`, so these keep
+    // their work in the helpers above to use little stack per level.
+    fn compound(&mut self) -> Result<Tag> {
+        let mut c = CompoundData::new();
+
+        if self.close(Token::RBrace)? {
+            return Ok(Tag::Compound(c));
+        }
+
+        loop {
+            let key = self.key()?;
+            let v = self.value()?;
+            c.insert(key, v);
+
+            if self.separator(Token::RBrace, "expected ',' or '}'")? {
+                return Ok(Tag::Compound(c));
+            }
+        }
+    }
+
+    // Handle the start of a list: an empty list, a typed array, or a first
+    // element that looked like an array type.
+    fn list_start(&mut self) -> Result<ListStart> {
+        match self.peek()? {
+            Some(Token::RBracket) => {
+                self.next()?;
+                Ok(ListStart::Empty)
+            },
+
+            // A leading `B;`, `I;` or `L;` makes this a typed array instead
+            Some(Token::Word(ref w)) if w == "B" || w == "I" || w == "L" => {
+                let (_, span) = self.next()?;
+
+                if self.close(Token::Semicolon)? {
+                    return self.array(w).map(ListStart::Array);
+                }
+
+                Ok(ListStart::Element(span.start, Tag::String(w.clone())))
+            },
+
+            _ => Ok(ListStart::Values)
+        }
+    }
+
+    fn list(&mut self) -> Result<Tag> {
+        let mut l = ListData::new(TagType::End);
+
+        let mut first = match self.list_start()? {
+            ListStart::Empty         => return Ok(Tag::List(l)),
+            ListStart::Array(a)      => return Ok(a),
+            ListStart::Element(s, v) => Some((s, v)),
+            ListStart::Values        => None
+        };
+
+        loop {
+            let (start, v) = match first.take() {
+                Some(x) => x,
+                None    => {
+                    let start = self.start();
+                    (start, self.value()?)
+                }
+            };
+
+            if l.elements.is_empty() {
+                l.element_type = v.get_type();
+            } else if v.get_type() != l.element_type {
+                return syntax_error(start, "list elements must all have the same type");
+            }
+
+            l.elements.push(v);
+
+            if self.separator(Token::RBracket, "expected ',' or ']'")? {
+                return Ok(Tag::List(l));
+            }
+        }
+    }
+
+    // Parse the elements of a typed array after its `B;`, `I;` or `L;`.
+    fn array(&mut self, kind: &str) -> Result<Tag> {
+        let mut values = Vec::new();
+
+        if self.peek()? == Some(Token::RBracket) {
+            self.next()?;
+        } else {
+            loop {
+                let start = self.start();
+
+                match (kind, self.value()?) {
+                    ("B", Tag::Byte(x)) => values.push(x as i64),
+                    ("I", Tag::Int(x))  => values.push(x as i64),
+                    ("L", Tag::Long(x)) => values.push(x),
+                    _ => return syntax_error(start, "array elements must match the array type")
+                }
+
+                let (t, span) = self.next()?;
+
+                match t {
+                    Token::Comma if self.peek()? == Some(Token::RBracket) => {
+                        self.next()?;
+                        break;
+                    },
+
+                    Token::Comma    => continue,
+                    Token::RBracket => break,
+                    _               => return syntax_error(span.start, "expected ',' or ']'")
+                }
+            }
+        }
+
+        Ok(match kind {
+            "B" => Tag::ByteArray(values.into_iter().map(|x| x as u8).collect()),
+            "I" => Tag::IntArray(values.into_iter().map(|x| x as i32).collect()),
+            _   => Tag::LongArray(values)
+        })
+    }
+
+    // The offset of the next token, for errors about the value it starts.
+    fn start(&mut self) -> usize {
+        match self.lexer.peek() {
+            Some(&Ok((_, span))) => span.start,
+            _                    => self.src.len()
+        }
+    }
+}
+
+// Convert a numeric literal to the tag its suffix (or lack thereof) calls
+// for, or `None` if it doesn't fit.
+fn number(value: &str, suffix: Option<char>) -> Option<Tag> {
+    let float = value.contains(['.', 'e', 'E']);
+
+    match suffix {
+        Some('b') => value.parse().ok().map(Tag::Byte),
+        Some('s') => value.parse().ok().map(Tag::Short),
+        Some('l') => value.parse().ok().map(Tag::Long),
+        Some('f') => value.parse().ok().map(Tag::Float),
+        Some('d') => value.parse().ok().map(Tag::Double),
+        _ if float => value.parse().ok().map(Tag::Double),
+        _          => value.parse().ok().map(Tag::Int)
+    }
+}
+
+/// Parse an SNBT string into the tag it describes.
+///
+/// Any ASCII whitespace may appear between tokens, and compounds, lists and
+/// arrays may end with a trailing comma. Numbers take their type from their
+/// suffix (`b`, `s`, `l`, `f` or `d`), defaulting to `Int`, or `Double` if
+/// they have a fraction or exponent. `[B;...]`, `[I;...]` and `[L;...]` are
+/// byte, int and long arrays, and lists must not mix element types.
+///
+/// Syntax errors are reported as `Error::Syntax` with the byte offset of the
+/// offending token. Compounds and lists may nest at most 512 deep.
+pub fn parse_snbt(src: &str) -> Result<Tag> {
+    let mut p = Parser {
+        src,
+        lexer: Lexer::new(src).peekable(),
+        depth: 0
+    };
+
+    let v = p.value()?;
+
+    match p.lexer.next() {
+        None                => Ok(v),
+        Some(Ok((_, span))) => syntax_error(span.start, "unexpected trailing input"),
+        Some(Err(e))        => Err(e)
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _    => out.push(c)
+        }
+    }
+
+    out.push('"');
+}
+
+fn write_key(out: &mut String, key: &str) {
+    if !key.is_empty() && key.chars().all(is_unquoted_char) {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_array<T: fmt::Display>(out: &mut String, prefix: &str, values: &[T], suffix: &str, spaced: bool) {
+    out.push('[');
+    out.push_str(prefix);
+    out.push(';');
+
+    for (i, v) in values.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        if spaced {
+            out.push(' ');
+        }
+
+        out.push_str(&format!("{}{}", v, suffix));
+    }
+
+    out.push(']');
+}
+
+/// Options for rendering SNBT with `Tag::to_snbt_with`. The defaults match
+/// the compact output of `Display`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SnbtFormat {
+    /// Put each compound entry and list element on its own line, indented by
+    /// this many spaces per level.
+    pub indent: Option<usize>,
+
+    /// Write `ByteArray` elements as unsigned numbers without a suffix, like
+    /// `[B;255]`, instead of the signed `[B;-1b]` vanilla writes. NBT bytes
+    /// are signed, but `Tag::ByteArray` stores them as `u8`.
+    pub unsigned_bytes: bool
+}
+
+// Render `tag` as SNBT. With an `indent`, compounds and lists are spread
+// over multiple lines, indented by `depth` levels.
+fn write_snbt(out: &mut String, tag: &Tag, format: &SnbtFormat, depth: usize) {
+    let indent = format.indent;
+    let spaced = indent.is_some();
+
+    let newline = |out: &mut String, depth: usize| if let Some(n) = indent {
+        out.push('\n');
+
+        for _ in 0 .. n * depth {
+            out.push(' ');
+        }
+    };
+
+    match *tag {
+        Tag::End              => (),
+        Tag::Byte(x)          => out.push_str(&format!("{}b", x)),
+        Tag::Short(x)         => out.push_str(&format!("{}s", x)),
+        Tag::Int(x)           => out.push_str(&x.to_string()),
+        Tag::Long(x)          => out.push_str(&format!("{}L", x)),
+        Tag::Float(x)         => out.push_str(&format!("{:?}f", x)),
+        Tag::Double(x)        => out.push_str(&format!("{:?}d", x)),
+        Tag::String(ref x)    => write_string(out, x),
+        Tag::ByteArray(ref x) if format.unsigned_bytes => write_array(out, "B", x, "", spaced),
+        Tag::ByteArray(ref x) => {
+            let signed: Vec<i8> = x.iter().map(|&b| b as i8).collect();
+            write_array(out, "B", &signed, "b", spaced)
+        },
+        Tag::IntArray(ref x)  => write_array(out, "I", x, "", spaced),
+        Tag::LongArray(ref x) => write_array(out, "L", x, "L", spaced),
+
+        Tag::List(ref x) => {
+            out.push('[');
+
+            for (i, v) in x.elements.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+
+                newline(out, depth + 1);
+                write_snbt(out, v, format, depth + 1);
+            }
+
+            if !x.elements.is_empty() {
+                newline(out, depth);
+            }
+
+            out.push(']');
+        },
+
+        Tag::Compound(ref x) => {
+            let mut keys: Vec<&String> = x.elements.keys().collect();
+            keys.sort();
+
+            out.push('{');
+
+            for (i, k) in keys.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+
+                newline(out, depth + 1);
+                write_key(out, k);
+                out.push_str(if spaced { ": " } else { ":" });
+                write_snbt(out, &x.elements[*k], format, depth + 1);
+            }
+
+            if !keys.is_empty() {
+                newline(out, depth);
+            }
+
+            out.push('}');
+        }
+    }
+}
+
+/// Renders the tag as compact SNBT, with compound keys in sorted order.
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = String::new();
+        write_snbt(&mut out, self, &SnbtFormat::default(), 0);
+
+        f.write_str(&out)
+    }
+}
+
+impl Tag {
+    /// Render the tag as SNBT with each compound entry and list element on
+    /// its own line, indented by `indent` spaces per level.
+    pub fn to_snbt_pretty(&self, indent: usize) -> String {
+        self.to_snbt_with(&SnbtFormat { indent: Some(indent), ..SnbtFormat::default() })
+    }
+
+    /// Render the tag as SNBT in the given format.
+    pub fn to_snbt_with(&self, format: &SnbtFormat) -> String {
+        let mut out = String::new();
+        write_snbt(&mut out, self, format, 0);
+
+        out
+    }
+}
+
+#[test]
+fn test_lexer() {
+    let toks: Vec<_> = Lexer::new("{Name:\"St\\\"eve\", Pos:[1.5d,-2b]}")
+        .map(|t| t.unwrap())
+        .collect();
+
+    let sp = |s, e| Span { start: s, end: e };
+
+    assert_eq!(toks, vec![
+        (Token::LBrace, sp(0, 1)),
+        (Token::Word("Name".to_owned()), sp(1, 5)),
+        (Token::Colon, sp(5, 6)),
+        (Token::String("St\"eve".to_owned()), sp(6, 15)),
+        (Token::Comma, sp(15, 16)),
+        (Token::Word("Pos".to_owned()), sp(17, 20)),
+        (Token::Colon, sp(20, 21)),
+        (Token::LBracket, sp(21, 22)),
+        (Token::Number { value: "1.5".to_owned(), suffix: Some('d') }, sp(22, 26)),
+        (Token::Comma, sp(26, 27)),
+        (Token::Number { value: "-2".to_owned(), suffix: Some('b') }, sp(27, 30)),
+        (Token::RBracket, sp(30, 31)),
+        (Token::RBrace, sp(31, 32)),
+    ]);
+
+    match Lexer::new("{a:\"open").last() {
+        Some(Err(Error::Syntax { offset, .. })) => assert_eq!(offset, 3),
+        x => panic!("unexpected {:?}", x)
+    }
+}
+
+#[test]
+fn test_parse_snbt() {
+    let tag = parse_snbt("{Name:\"Steve\", Health:20.0f, Pos:[1.0d, 2.5, 3e1], ok:true, n:{}}").unwrap();
+
+    let mut pos = ListData::new(TagType::Double);
+    pos.extend(vec![Tag::Double(1.0), Tag::Double(2.5), Tag::Double(30.0)]);
+
+    let mut c = CompoundData::new();
+    c.insert("Name".to_owned(), Tag::String("Steve".to_owned()));
+    c.insert("Health".to_owned(), Tag::Float(20.0));
+    c.insert("Pos".to_owned(), Tag::List(pos));
+    c.insert("ok".to_owned(), Tag::Byte(1));
+    c.insert("n".to_owned(), Tag::Compound(CompoundData::new()));
+
+    assert_eq!(tag, Tag::Compound(c));
+    assert_eq!(parse_snbt("[]").unwrap(), Tag::List(ListData::new(TagType::End)));
+    assert_eq!(parse_snbt("-5s").unwrap(), Tag::Short(-5));
+}
+
+#[test]
+fn test_parse_snbt_errors() {
+    let offset = |s| match parse_snbt(s) {
+        Err(Error::Syntax { offset, message }) => (offset, message),
+        x => panic!("unexpected {:?}", x)
+    };
+
+    assert_eq!(offset("{a 1}"), (3, "expected ':'".to_owned()));
+    assert_eq!(offset("{a:1"), (4, "unexpected end of input".to_owned()));
+    assert_eq!(offset("{:1}").0, 1);
+    assert_eq!(offset("{a:1}x").0, 5);
+    assert_eq!(offset("[1, 2b]").0, 4);
+    assert_eq!(offset("{a:[1 2]}").0, 6);
+    assert_eq!(offset("{a:300b}").0, 3);
+    assert_eq!(offset("{a:\"x}").0, 3);
+    assert_eq!(offset("{a:1,,}").0, 5);
+    assert_eq!(offset("[1,,]").0, 3);
+    assert_eq!(offset("[,]").0, 1);
+}
+
+#[test]
+fn test_parse_snbt_arrays() {
+    assert_eq!(parse_snbt("[B; 1b, -1b, true]").unwrap(), Tag::ByteArray(vec![1, 255, 1]));
+    assert_eq!(parse_snbt("[I;1,-2,3]").unwrap(), Tag::IntArray(vec![1, -2, 3]));
+    assert_eq!(parse_snbt("[L;5l]").unwrap(), Tag::LongArray(vec![5]));
+    assert_eq!(parse_snbt("[I;]").unwrap(), Tag::IntArray(vec![]));
+
+    // Without the semicolon it's just a list of strings
+    let mut l = ListData::new(TagType::String);
+    l.extend(vec![Tag::String("B".to_owned()), Tag::String("x".to_owned())]);
+    assert_eq!(parse_snbt("[B, x]").unwrap(), Tag::List(l));
+
+    match parse_snbt("[I; 1, 2b]") {
+        Err(Error::Syntax { offset, .. }) => assert_eq!(offset, 7),
+        x => panic!("unexpected {:?}", x)
+    }
+}
+
+#[test]
+fn test_parse_snbt_nested() {
+    let tag = parse_snbt("{Name:\"Steve\",Health:20.0f,Inventory:[{Slot:0b}],m:[[1],[2,3],[]]}").unwrap();
+
+    assert_eq!(tag.clone_path("Inventory[0].Slot"), Some(Tag::Byte(0)));
+    assert_eq!(tag.clone_path("m[1][1]"), Some(Tag::Int(3)));
+    assert_eq!(tag.clone_path("m[2]"), Some(Tag::List(ListData::new(TagType::End))));
+
+    assert!(parse_snbt("[[1], [1b]]").is_ok());
+    assert!(parse_snbt("[[1], {}]").is_err());
+
+    let deep = |n| "[".repeat(n) + &"]".repeat(n);
+    assert!(parse_snbt(&deep(MAX_DEPTH)).is_ok());
+
+    match parse_snbt(&"[".repeat(200_000)) {
+        Err(Error::Syntax { offset, .. }) => assert_eq!(offset, 512),
+        x                                 => panic!("unexpected {:?}", x)
+    }
+
+    match parse_snbt(&"{a:".repeat(600)) {
+        Err(Error::Syntax { offset, .. }) => assert_eq!(offset, 512 * 3),
+        x                                 => panic!("unexpected {:?}", x)
+    }
+}
+
+#[test]
+fn test_parse_snbt_escapes() {
+    assert_eq!(parse_snbt(r#""a\\b\"c\'d""#).unwrap(), Tag::String("a\\b\"c'd".to_owned()));
+    assert_eq!(parse_snbt(r#"'line\nnext\ttab\u00e9'"#).unwrap(),
+               Tag::String("line\nnext\ttab\u{e9}".to_owned()));
+    assert!(parse_snbt(r#""bad\q""#).is_err());
+    assert!(parse_snbt(r#""bad\u12""#).is_err());
+}
+
+#[test]
+fn test_parse_snbt_lenient() {
+    let expected = parse_snbt("{a:1,b:[1,2],c:[I;3]}").unwrap();
+
+    assert_eq!(parse_snbt("  {\r\n\ta : 1 ,\r\n\tb:[ 1 ,2 ] ,c :[I; 3]\r\n}\r\n").unwrap(), expected);
+    assert_eq!(parse_snbt("{a:1,b:[1,2,],c:[I;3,],}").unwrap(), expected);
+    assert_eq!(parse_snbt("{\n  a: 1,\n  b: [\n    1,\n    2,\n  ],\n  c: [I; 3],\n}").unwrap(), expected);
+}
+
+#[test]
+fn test_display() {
+    let src = "{\"a b\":\"say \\\"hi\\\"\",arr:[B;-1b,2b],f:20.0f,i:[I;1,-2],l:[L;9999999999L],\
+               list:[{Slot:0b},{}],long:9999999999L,n:[],s:3s,x:1.5d,y:7}";
+
+    let tag = parse_snbt(src).unwrap();
+    assert_eq!(tag.to_string(), src);
+    assert_eq!(parse_snbt(&tag.to_snbt_pretty(4)).unwrap(), tag);
+
+    let tag = parse_snbt("{b:{},a:[1,2],c:[I;3,4]}").unwrap();
+    assert_eq!(tag.to_snbt_pretty(2), "{\n  a: [\n    1,\n    2\n  ],\n  b: {},\n  c: [I; 3, 4]\n}");
+}
+
+#[test]
+fn test_unsigned_bytes() {
+    let tag = Tag::ByteArray(vec![0, 0xFF]);
+    let unsigned = SnbtFormat { unsigned_bytes: true, ..SnbtFormat::default() };
+
+    assert_eq!(tag.to_string(), "[B;0b,-1b]");
+    assert_eq!(tag.to_snbt_with(&SnbtFormat::default()), "[B;0b,-1b]");
+    assert_eq!(tag.to_snbt_with(&unsigned), "[B;0,255]");
+    assert_eq!(Tag::Byte(-1).to_snbt_with(&unsigned), "-1b");
+}
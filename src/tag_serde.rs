@@ -0,0 +1,862 @@
+//! Convert between tags and types implementing serde's `Serialize` and
+//! `Deserialize`. Only available with the `serde` feature.
+//!
+//! Values map onto tags as follows:
+//!
+//! * `bool`, `i8` and `u8` become `Byte`s, with `u8` reinterpreted as signed
+//!   and read back the same way, so byte buffers keep their bits.
+//! * `i16`, `i32`, `i64`, `f32` and `f64` become the matching tag, `u16`
+//!   and `u32` the next larger signed one. `u64` becomes a `Long` if it fits.
+//! * Strings and chars become `String`s, byte slices `ByteArray`s.
+//! * Sequences become lists, except that sequences of only `Byte`, `Int` or
+//!   `Long` values become the matching array, so `Vec<u8>`, `Vec<i32>` and
+//!   `Vec<i64>` map to arrays. Lists of those types inside a `Tag` are kept
+//!   as lists by `to_tag`.
+//! * Structs and maps with string keys become compounds. `None` becomes
+//!   `Tag::End`, and fields or entries with that value are left out.
+//! * Enums are externally tagged: unit variants become the variant's name as
+//!   a `String`, other variants a compound with the variant's name as the
+//!   only key.
+//!
+//! `Tag` itself implements `Serialize` and `Deserialize`, so it converts to
+//! and from other formats. Since those don't know about arrays, sequences of
+//! numbers of one type come back as arrays.
+
+use std;
+
+use super::{Error, Result, Tag, TagType, ListData, CompoundData};
+
+use serde::ser::{self, Serialize};
+use serde::de::{self, Deserialize, IntoDeserializer, Visitor};
+use serde::de::value::{BorrowedStrDeserializer, SeqDeserializer};
+
+use std::fmt;
+
+// Newtype name `Tag` uses for lists, so `to_tag` doesn't turn lists of
+// numbers into arrays. Transparent to other serializers.
+const LIST_TOKEN: &str = "__nbt_list";
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Serde(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Serde(msg.to_string())
+    }
+}
+
+/// Convert `value` to a tag.
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> Result<Tag> {
+    value.serialize(Serializer)
+}
+
+/// Convert `tag` to a value of type `T`.
+pub fn from_tag<'de, T: Deserialize<'de>>(tag: &'de Tag) -> Result<T> {
+    T::deserialize(Deserializer::new(tag))
+}
+
+
+impl Serialize for Tag {
+    fn serialize<S: ser::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        match *self {
+            Tag::End       => s.serialize_unit(),
+            Tag::Byte(x)   => s.serialize_i8(x),
+            Tag::Short(x)  => s.serialize_i16(x),
+            Tag::Int(x)    => s.serialize_i32(x),
+            Tag::Long(x)   => s.serialize_i64(x),
+            Tag::Float(x)  => s.serialize_f32(x),
+            Tag::Double(x) => s.serialize_f64(x),
+
+            Tag::String(ref x)    => s.serialize_str(x),
+            Tag::ByteArray(ref x) => s.collect_seq(x.iter().map(|&b| b as i8)),
+            Tag::IntArray(ref x)  => s.collect_seq(x),
+            Tag::LongArray(ref x) => s.collect_seq(x),
+            Tag::List(ref x)      => s.serialize_newtype_struct(LIST_TOKEN, &x.elements),
+            Tag::Compound(ref x)  => s.collect_map(x.elements.iter())
+        }
+    }
+}
+
+// Build a list, or an array if all elements are `Byte`, `Int` or `Long`.
+fn seq_to_tag(elements: Vec<Tag>, arrays: bool) -> Result<Tag> {
+    let element_type = elements.first().map_or(TagType::End, |e| e.get_type());
+
+    if element_type == TagType::End && !elements.is_empty() {
+        return Err(Error::Serde("sequence holds absent values".to_owned()));
+    }
+
+    if elements.iter().any(|e| e.get_type() != element_type) {
+        return Err(Error::Serde("sequence elements have different types".to_owned()));
+    }
+
+    if arrays && !elements.is_empty() {
+        match element_type {
+            TagType::Byte => return Ok(Tag::ByteArray(elements.into_iter().map(|e| match e {
+                Tag::Byte(x) => x as u8,
+                _            => unreachable!()
+            }).collect())),
+
+            TagType::Int => return Ok(Tag::IntArray(elements.into_iter().map(|e| match e {
+                Tag::Int(x) => x,
+                _           => unreachable!()
+            }).collect())),
+
+            TagType::Long => return Ok(Tag::LongArray(elements.into_iter().map(|e| match e {
+                Tag::Long(x) => x,
+                _            => unreachable!()
+            }).collect())),
+
+            _ => ()
+        }
+    }
+
+    Ok(Tag::List(ListData {
+        element_type,
+        elements
+    }))
+}
+
+fn variant(name: &str, value: Tag) -> Tag {
+    let mut c = CompoundData::new();
+    c.insert(name.to_owned(), value);
+
+    Tag::Compound(c)
+}
+
+/// Serializer producing a `Tag`. See `to_tag`.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Tag;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Tag> { Ok(Tag::Byte(v as i8)) }
+    fn serialize_i8(self, v: i8) -> Result<Tag> { Ok(Tag::Byte(v)) }
+    fn serialize_i16(self, v: i16) -> Result<Tag> { Ok(Tag::Short(v)) }
+    fn serialize_i32(self, v: i32) -> Result<Tag> { Ok(Tag::Int(v)) }
+    fn serialize_i64(self, v: i64) -> Result<Tag> { Ok(Tag::Long(v)) }
+    fn serialize_u8(self, v: u8) -> Result<Tag> { Ok(Tag::Byte(v as i8)) }
+    fn serialize_u16(self, v: u16) -> Result<Tag> { Ok(Tag::Int(v as i32)) }
+    fn serialize_u32(self, v: u32) -> Result<Tag> { Ok(Tag::Long(v as i64)) }
+    fn serialize_f32(self, v: f32) -> Result<Tag> { Ok(Tag::Float(v)) }
+    fn serialize_f64(self, v: f64) -> Result<Tag> { Ok(Tag::Double(v)) }
+
+    fn serialize_u64(self, v: u64) -> Result<Tag> {
+        if v > i64::MAX as u64 {
+            Err(Error::Serde(format!("{} doesn't fit in a Long", v)))
+        } else {
+            Ok(Tag::Long(v as i64))
+        }
+    }
+
+    fn serialize_char(self, v: char) -> Result<Tag> {
+        Ok(Tag::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Tag> {
+        Ok(Tag::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Tag> {
+        Ok(Tag::ByteArray(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Tag> {
+        Ok(Tag::End)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Tag> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Tag> {
+        Ok(Tag::End)
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Tag> {
+        Ok(Tag::End)
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<Tag> {
+        Ok(Tag::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<Tag> {
+        if name == LIST_TOKEN {
+            // Undo the conversion of lists of numbers to arrays
+            let (element_type, elements) = match value.serialize(Serializer)? {
+                Tag::ByteArray(x) => (TagType::Byte, x.into_iter().map(|b| Tag::Byte(b as i8)).collect()),
+                Tag::IntArray(x)  => (TagType::Int, x.into_iter().map(Tag::Int).collect()),
+                Tag::LongArray(x) => (TagType::Long, x.into_iter().map(Tag::Long).collect()),
+                other             => return Ok(other)
+            };
+
+            Ok(Tag::List(ListData {
+                element_type,
+                elements
+            }))
+        } else {
+            value.serialize(self)
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32,
+                                                        variant: &'static str, value: &T) -> Result<Tag> {
+        Ok(self::variant(variant, value.serialize(Serializer)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer { elements: Vec::with_capacity(len.unwrap_or(0)), arrays: true })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, variant: &'static str,
+                               len: usize) -> Result<VariantSerializer<SeqSerializer>> {
+        Ok(VariantSerializer { variant, inner: self.serialize_seq(Some(len))? })
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer { map: CompoundData::new(), key: None })
+    }
+
+    fn serialize_struct(self, _: &'static str, len: usize) -> Result<MapSerializer> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _: &'static str, _: u32, variant: &'static str,
+                                len: usize) -> Result<VariantSerializer<MapSerializer>> {
+        Ok(VariantSerializer { variant, inner: self.serialize_map(Some(len))? })
+    }
+}
+
+/// Serializer for sequences, building a list or array.
+pub struct SeqSerializer {
+    elements: Vec<Tag>,
+    arrays: bool
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.elements.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Tag> {
+        seq_to_tag(self.elements, self.arrays)
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        self.finish()
+    }
+}
+
+/// Serializer for maps and structs, building a compound.
+pub struct MapSerializer {
+    map: CompoundData,
+    key: Option<String>
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<()> {
+        match value.serialize(Serializer)? {
+            Tag::End => (),
+            v        => { self.map.insert(key, v); }
+        }
+
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        match key.serialize(Serializer)? {
+            Tag::String(k) => {
+                self.key = Some(k);
+                Ok(())
+            },
+
+            _ => Err(Error::Serde("compound keys must be strings".to_owned()))
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().expect("serialize_value called before serialize_key");
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        Ok(Tag::Compound(self.map))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        Ok(Tag::Compound(self.map))
+    }
+}
+
+/// Serializer for enum variants with fields, wrapping them in a compound
+/// keyed by the variant's name.
+pub struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        Ok(variant(self.variant, self.inner.finish()?))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Tag;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.inner.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Tag> {
+        Ok(variant(self.variant, Tag::Compound(self.inner.map)))
+    }
+}
+
+
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an NBT value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> std::result::Result<Tag, E> { Ok(Tag::Byte(v as i8)) }
+    fn visit_i8<E>(self, v: i8) -> std::result::Result<Tag, E> { Ok(Tag::Byte(v)) }
+    fn visit_i16<E>(self, v: i16) -> std::result::Result<Tag, E> { Ok(Tag::Short(v)) }
+    fn visit_i32<E>(self, v: i32) -> std::result::Result<Tag, E> { Ok(Tag::Int(v)) }
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<Tag, E> { Ok(Tag::Long(v)) }
+    fn visit_u8<E>(self, v: u8) -> std::result::Result<Tag, E> { Ok(Tag::Byte(v as i8)) }
+    fn visit_u16<E>(self, v: u16) -> std::result::Result<Tag, E> { Ok(Tag::Int(v as i32)) }
+    fn visit_u32<E>(self, v: u32) -> std::result::Result<Tag, E> { Ok(Tag::Long(v as i64)) }
+    fn visit_f32<E>(self, v: f32) -> std::result::Result<Tag, E> { Ok(Tag::Float(v)) }
+    fn visit_f64<E>(self, v: f64) -> std::result::Result<Tag, E> { Ok(Tag::Double(v)) }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<Tag, E> {
+        if v > i64::MAX as u64 {
+            Err(E::custom(format!("{} doesn't fit in a Long", v)))
+        } else {
+            Ok(Tag::Long(v as i64))
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Tag, E> {
+        Ok(Tag::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> std::result::Result<Tag, E> {
+        Ok(Tag::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Tag, E> {
+        Ok(Tag::ByteArray(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Tag, E> {
+        Ok(Tag::ByteArray(v))
+    }
+
+    fn visit_none<E>(self) -> std::result::Result<Tag, E> {
+        Ok(Tag::End)
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<Tag, E> {
+        Ok(Tag::End)
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, d: D) -> std::result::Result<Tag, D::Error> {
+        Tag::deserialize(d)
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, d: D) -> std::result::Result<Tag, D::Error> {
+        Tag::deserialize(d)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Tag, A::Error> {
+        let mut elements = Vec::new();
+
+        while let Some(e) = seq.next_element()? {
+            elements.push(e);
+        }
+
+        seq_to_tag(elements, true).map_err(de::Error::custom)
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> std::result::Result<Tag, A::Error> {
+        let mut c = CompoundData::new();
+
+        // Absent values, like JSON's null, leave out their key
+        while let Some((k, v)) = map.next_entry()? {
+            match v {
+                Tag::End => (),
+                v        => { c.insert(k, v); }
+            }
+        }
+
+        Ok(Tag::Compound(c))
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: de::Deserializer<'de>>(d: D) -> std::result::Result<Tag, D::Error> {
+        d.deserialize_any(TagVisitor)
+    }
+}
+
+/// Deserializer reading from a `Tag`. See `from_tag`.
+pub struct Deserializer<'de> {
+    tag: &'de Tag
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create a deserializer reading from `tag`.
+    pub fn new(tag: &'de Tag) -> Deserializer<'de> {
+        Deserializer { tag }
+    }
+}
+
+struct ListAccess<'de> {
+    iter: std::slice::Iter<'de, Tag>
+}
+
+impl<'de> de::SeqAccess<'de> for ListAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.iter.next() {
+            Some(e) => seed.deserialize(Deserializer::new(e)).map(Some),
+            None    => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct ByteArrayAccess<'de> {
+    iter: std::slice::Iter<'de, u8>
+}
+
+impl<'de> de::SeqAccess<'de> for ByteArrayAccess<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.iter.next() {
+            Some(&b) => seed.deserialize(ByteDeserializer(b as i8)).map(Some),
+            None     => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct CompoundAccess<'de, I> {
+    iter: I,
+    value: Option<&'de Tag>
+}
+
+impl<'de, I> de::MapAccess<'de> for CompoundAccess<'de, I>
+    where I: Iterator<Item = (&'de String, &'de Tag)> {
+
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                seed.deserialize(BorrowedStrDeserializer::new(k)).map(Some)
+            },
+
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let v = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Deserializer::new(v))
+    }
+}
+
+struct VariantAccess<'de> {
+    name: &'de str,
+    value: &'de Tag
+}
+
+impl<'de> de::EnumAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let v = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.name))?;
+        Ok((v, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Deserialize::deserialize(Deserializer::new(self.value))
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(Deserializer::new(self.value))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_seq(Deserializer::new(self.value), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_map(Deserializer::new(self.value), visitor)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match *self.tag {
+            Tag::End       => visitor.visit_unit(),
+            Tag::Byte(x)   => visitor.visit_i8(x),
+            Tag::Short(x)  => visitor.visit_i16(x),
+            Tag::Int(x)    => visitor.visit_i32(x),
+            Tag::Long(x)   => visitor.visit_i64(x),
+            Tag::Float(x)  => visitor.visit_f32(x),
+            Tag::Double(x) => visitor.visit_f64(x),
+
+            Tag::String(ref x) => visitor.visit_borrowed_str(x),
+
+            Tag::ByteArray(ref x) => visitor.visit_seq(ByteArrayAccess { iter: x.iter() }),
+
+            Tag::IntArray(ref x) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(x.iter().cloned());
+                let v = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(v)
+            },
+
+            Tag::LongArray(ref x) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(x.iter().cloned());
+                let v = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(v)
+            },
+
+            Tag::List(ref x) => visitor.visit_seq(ListAccess { iter: x.elements.iter() }),
+
+            Tag::Compound(ref x) => visitor.visit_map(CompoundAccess { iter: x.elements.iter(), value: None })
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match *self.tag {
+            Tag::Byte(x) => visitor.visit_bool(x != 0),
+            _            => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match *self.tag {
+            Tag::Byte(x) => visitor.visit_u8(x as u8),
+            _            => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match *self.tag {
+            Tag::ByteArray(ref x) => visitor.visit_borrowed_bytes(x),
+            _                     => self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match *self.tag {
+            Tag::End => visitor.visit_none(),
+            _        => visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _: &'static str, _: &'static [&'static str],
+                                         visitor: V) -> Result<V::Value> {
+        match *self.tag {
+            Tag::String(ref x) => visitor.visit_enum(x.as_str().into_deserializer()),
+
+            Tag::Compound(ref x) if x.elements.len() == 1 => {
+                let (name, value) = x.elements.iter().next().unwrap();
+                visitor.visit_enum(VariantAccess { name, value })
+            },
+
+            _ => Err(Error::Serde("expected a string or a compound with one entry for an enum".to_owned()))
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+// Deserializer for the elements of a `ByteArray`, which may be read as
+// either signed or unsigned bytes.
+struct ByteDeserializer(i8);
+
+impl<'de> de::Deserializer<'de> for ByteDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_bool(self.0 != 0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.0 as u8)
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[cfg(feature = "json")]
+impl Tag {
+    /// Convert the tag to a JSON value, with the `json` feature.
+    ///
+    /// This is a lossy export meant for reading and tools like `jq`: numbers
+    /// lose their tag type, arrays become JSON arrays of numbers (signed, for
+    /// byte arrays), and non-finite floats become `null`.
+    pub fn to_json(&self) -> ::serde_json::Value {
+        ::serde_json::to_value(self).unwrap_or(::serde_json::Value::Null)
+    }
+
+    /// Convert a JSON value to a tag, with the `json` feature.
+    ///
+    /// Since JSON doesn't record tag types, integers become `Long`s and other
+    /// numbers `Double`s, booleans `Byte`s, and arrays of integers
+    /// `LongArray`s. Other arrays must hold values of a single type. `null`
+    /// is only allowed as an object value, and leaves out the key.
+    pub fn from_json(value: &::serde_json::Value) -> Result<Tag> {
+        match Tag::deserialize(value) {
+            Ok(Tag::End) => Err(Error::Serde("null can't be converted to a tag".to_owned())),
+            Ok(t)        => Ok(t),
+            Err(e)       => Err(Error::Serde(e.to_string()))
+        }
+    }
+}
+
+#[test]
+fn test_serde_roundtrip() {
+    use serde_derive::{Serialize, Deserialize};
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Kind {
+        Passive,
+        Hostile { range: f32 },
+        Tamed(String)
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Mob {
+        #[serde(rename = "Health")]
+        health: f32,
+        name: Option<String>,
+        kind: Kind,
+        kinds: Vec<Kind>,
+        pos: Vec<f64>,
+        light: Vec<u8>,
+        heights: Vec<i64>,
+        flags: HashMap<String, bool>
+    }
+
+    let mut flags = HashMap::new();
+    flags.insert("NoAI".to_owned(), true);
+
+    let mob = Mob {
+        health: 20.0,
+        name: None,
+        kind: Kind::Passive,
+        kinds: vec![Kind::Hostile { range: 16.0 }, Kind::Tamed("Steve".to_owned())],
+        pos: vec![0.5, 64.0, -2.5],
+        light: vec![0, 15, 255],
+        heights: vec![1, 2, 3],
+        flags
+    };
+
+    let tag = to_tag(&mob).unwrap();
+
+    assert_eq!(tag.get_path("Health"), Some(&Tag::Float(20.0)));
+    assert_eq!(tag.get_path("name"), None);
+    assert_eq!(tag.get_path("kind"), Some(&Tag::String("Passive".to_owned())));
+    assert_eq!(tag.get_path("kinds[0].Hostile.range"), Some(&Tag::Float(16.0)));
+    assert_eq!(tag.get_path("kinds[1].Tamed"), Some(&Tag::String("Steve".to_owned())));
+    assert_eq!(tag.get_path("pos[1]"), Some(&Tag::Double(64.0)));
+    assert_eq!(tag.get_path("light"), Some(&Tag::ByteArray(vec![0, 15, 255])));
+    assert_eq!(tag.get_path("heights"), Some(&Tag::LongArray(vec![1, 2, 3])));
+    assert_eq!(tag.get_path("flags.NoAI"), Some(&Tag::Byte(1)));
+
+    assert_eq!(from_tag::<Mob>(&tag).unwrap(), mob);
+}
+
+#[test]
+fn test_serde_tag() {
+    let mut list = ListData::new(TagType::Int);
+    list.elements.extend(vec![Tag::Int(1), Tag::Int(2)]);
+
+    let mut c = CompoundData::new();
+    c.insert("list".to_owned(), Tag::List(list));
+    c.insert("ints".to_owned(), Tag::IntArray(vec![1, 2]));
+    c.insert("bytes".to_owned(), Tag::ByteArray(vec![200]));
+    c.insert("name".to_owned(), Tag::String("x".to_owned()));
+
+    let tag = Tag::Compound(c);
+
+    // Lists of numbers stay lists going through a serializer...
+    assert_eq!(to_tag(&tag).unwrap(), tag);
+
+    // ...but become arrays when read back generically
+    let back: Tag = from_tag(&tag).unwrap();
+    assert_eq!(back.get_path("list"), Some(&Tag::IntArray(vec![1, 2])));
+    assert_eq!(back.get_path("bytes"), Some(&Tag::ByteArray(vec![200])));
+
+    assert_eq!(from_tag::<Vec<u8>>(&Tag::ByteArray(vec![200])).unwrap(), vec![200]);
+    assert_eq!(from_tag::<Vec<i8>>(&Tag::ByteArray(vec![200])).unwrap(), vec![-56]);
+    assert!(from_tag::<String>(&Tag::Int(1)).is_err());
+    assert!(to_tag(&vec![Some(1), None]).is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json() {
+    let mut list = ListData::new(TagType::String);
+    list.elements.push(Tag::String("a".to_owned()));
+
+    let mut c = CompoundData::new();
+    c.insert("Health".to_owned(), Tag::Float(20.0));
+    c.insert("Tags".to_owned(), Tag::List(list.clone()));
+    c.insert("Light".to_owned(), Tag::ByteArray(vec![255, 1]));
+    c.insert("Seed".to_owned(), Tag::Long(-1234567890123));
+
+    let tag = Tag::Compound(c);
+    let json = tag.to_json();
+
+    assert_eq!(json["Health"], 20.0);
+    assert_eq!(json["Tags"][0], "a");
+    assert_eq!(json["Light"], ::serde_json::json!([-1, 1]));
+    assert_eq!(json["Seed"], -1234567890123_i64);
+
+    let back = Tag::from_json(&json).unwrap();
+    assert_eq!(back.get_path("Health"), Some(&Tag::Double(20.0)));
+    assert_eq!(back.get_path("Tags"), Some(&Tag::List(list)));
+    assert_eq!(back.get_path("Seed"), Some(&Tag::Long(-1234567890123)));
+
+    assert!(Tag::from_json(&::serde_json::json!([1, "a"])).is_err());
+    assert!(Tag::from_json(&::serde_json::Value::Null).is_err());
+
+    let nulls = Tag::from_json(&::serde_json::json!({ "a": 1, "b": null })).unwrap();
+    let mut expected = CompoundData::new();
+    expected.insert("a".to_owned(), Tag::Long(1));
+
+    assert_eq!(nulls, Tag::Compound(expected));
+    assert!(::encode::to_bytes("", &nulls).is_ok());
+}
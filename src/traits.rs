@@ -1,6 +1,6 @@
 //! Traits for working with NBT data.
 
-use super::types::{Tag, TagType, CompoundData, ListData};
+use super::types::{Tag, TagType, CompoundData, ListData, Error, Result};
 
 use std::collections::HashMap;
 
@@ -8,6 +8,14 @@ use std::collections::HashMap;
 pub trait ToNbt {
     /// Encode `self` as an NBT tag.
     fn to_nbt(&self) -> Tag;
+
+    /// Encode `self` as an NBT tag, failing if it can't be represented.
+    ///
+    /// Only needed for types whose `to_nbt` can fail; by default this
+    /// always succeeds.
+    fn try_to_nbt(&self) -> Result<Tag> {
+        Ok(self.to_nbt())
+    }
 }
 
 macro_rules! tonbt_impl {
@@ -27,61 +35,240 @@ tonbt_impl!(f32, Tag::Float);
 tonbt_impl!(f64, Tag::Double);
 tonbt_impl!(String, Tag::String);
 
+// NBT has no unsigned types, so these widen to the next signed tag that can
+// hold every value.
+macro_rules! tonbt_unsigned_impl {
+        ($t:ty, $e:path, $w:ty) => { impl ToNbt for $t {
+            fn to_nbt(&self) -> Tag {
+                $e(*self as $w)
+            }
+        }
+    }
+}
+
+tonbt_unsigned_impl!(u8, Tag::Short, i16);
+tonbt_unsigned_impl!(u16, Tag::Int, i32);
+tonbt_unsigned_impl!(u32, Tag::Long, i64);
+
+impl ToNbt for str {
+    fn to_nbt(&self) -> Tag {
+        Tag::String(self.to_owned())
+    }
+}
+
+impl<T> ToNbt for &T
+    where T: ToNbt + ?Sized {
+
+    fn to_nbt(&self) -> Tag {
+        (**self).to_nbt()
+    }
+
+    fn try_to_nbt(&self) -> Result<Tag> {
+        (**self).try_to_nbt()
+    }
+}
+
+/// A tag converts to a copy of itself.
+impl ToNbt for Tag {
+    fn to_nbt(&self) -> Tag {
+        self.clone()
+    }
+}
+
+/// Booleans are stored as a `Byte` of 0 or 1.
+impl ToNbt for bool {
+    fn to_nbt(&self) -> Tag {
+        Tag::Byte(*self as i8)
+    }
+}
+
 impl<T> ToNbt for [T]
     where T: ToNbt {
 
+    /// Convert the slice to a list.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the elements don't all convert to the same tag type. Use
+    /// `try_to_nbt` to handle that case.
     fn to_nbt(&self) -> Tag {
-        if self.len() > 0 {
-            let mut iter = self.iter();
-            let first = iter.next().unwrap();
+        match self.try_to_nbt() {
+            Ok(x)  => x,
+            Err(_) => panic!("ToNbt for [T]: elements convert to different tag types")
+        }
+    }
 
-            let mut list = ListData {
-                element_type: first.to_nbt().get_type(),
-                elements: vec![first.to_nbt()]
-            };
+    /// Convert the slice to a list, failing with `Error::Invalid` if the
+    /// elements don't all convert to the same tag type.
+    fn try_to_nbt(&self) -> Result<Tag> {
+        let elements: Vec<Tag> = self.iter().map(|e| e.to_nbt()).collect();
 
-            for e in iter {
-                let d = e.to_nbt();
+        let element_type = match elements.first() {
+            Some(x) => x.get_type(),
+            None    => TagType::Byte
+        };
+
+        if elements.iter().any(|e| e.get_type() != element_type) {
+            return Err(Error::Invalid);
+        }
+
+        Ok(Tag::List(ListData {
+            element_type,
+            elements
+        }))
+    }
+}
+impl<T> ToNbt for Vec<T>
+    where T: ToNbt {
+
+    fn to_nbt(&self) -> Tag {
+        self[..].to_nbt()
+    }
+
+    fn try_to_nbt(&self) -> Result<Tag> {
+        self[..].try_to_nbt()
+    }
+}
 
-                if d.get_type() == list.element_type {
-                    list.elements.push(e.to_nbt());
-                }
-            }
 
-            Tag::List(list)
-        } else {
-            Tag::List(ListData {
-                element_type: TagType::Byte,
-                elements: Vec::new()
-            })
+/// `None` converts to `Tag::End`, which stands for an absent value. Compounds
+/// built with `ToNbt` omit entries that convert to `Tag::End`, so a `None`
+/// field leaves out its key entirely.
+impl<T> ToNbt for Option<T>
+    where T: ToNbt {
+
+    fn to_nbt(&self) -> Tag {
+        match *self {
+            Some(ref x) => x.to_nbt(),
+            None        => Tag::End
         }
     }
 }
 
 
-impl<'a, T> ToNbt for HashMap<String, T>
+/// Entries whose values convert to `Tag::End`, like `None`, are left out.
+impl<T> ToNbt for HashMap<String, T>
     where T: ToNbt {
 
     fn to_nbt(&self) -> Tag {
-        let mut cd = CompoundData { elements: HashMap::new() };
+        let mut cd = CompoundData::new();
 
         for (name, tag) in self {
-            cd.elements.insert(name.clone(), tag.to_nbt());
+            match tag.to_nbt() {
+                Tag::End => (),
+                t        => { cd.elements.insert(name.clone(), t); }
+            }
         }
 
         Tag::Compound(cd)
     }
 }
 
+// Support for the `nbt_eq` method generated by `#[derive(ToNbt)]`, which
+// compares fields with `==` where they implement `PartialEq` and by their NBT
+// otherwise. Method resolution tries `FieldEqDirect`, implemented on the
+// pair itself, before autoref reaches `FieldEqNbt`.
+#[doc(hidden)]
+pub struct FieldEq<'a, T: ?Sized + 'a>(pub &'a T, pub &'a T);
+
+#[doc(hidden)]
+pub trait FieldEqDirect {
+    fn field_eq(&self) -> bool;
+}
+
+impl<'a, T: PartialEq + ?Sized> FieldEqDirect for FieldEq<'a, T> {
+    fn field_eq(&self) -> bool {
+        self.0 == self.1
+    }
+}
+
+#[doc(hidden)]
+pub trait FieldEqNbt {
+    fn field_eq(&self) -> bool;
+}
+
+impl<'a, 'b, T: ToNbt + ?Sized> FieldEqNbt for &'b FieldEq<'a, T> {
+    fn field_eq(&self) -> bool {
+        self.0.to_nbt() == self.1.to_nbt()
+    }
+}
+
+// Converts `byte_array!` elements, which may be written as signed or
+// unsigned bytes. Untyped literals fall back to `i32`, which is accepted
+// over the range of either.
+#[doc(hidden)]
+pub trait ByteArrayElement {
+    fn to_byte(self) -> u8;
+}
+
+impl ByteArrayElement for i8 {
+    fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
+impl ByteArrayElement for u8 {
+    fn to_byte(self) -> u8 {
+        self
+    }
+}
+
+impl ByteArrayElement for i32 {
+    fn to_byte(self) -> u8 {
+        assert!((-128 ..= 255).contains(&self), "byte_array!: {} doesn't fit in a byte", self);
+        self as u8
+    }
+}
+
 #[test]
 fn test_tonbt() {
     assert!(42_i8.to_nbt() == Tag::Byte(42));
     assert!("test".to_owned().to_nbt() == Tag::String("test".to_owned()));
 }
 
+#[test]
+fn test_slice_tonbt() {
+    struct Either(bool);
+
+    impl ToNbt for Either {
+        fn to_nbt(&self) -> Tag {
+            if self.0 { Tag::Int(1) } else { Tag::String("no".to_owned()) }
+        }
+    }
+
+    let ints = [1_i32, 2, 3][..].to_nbt();
+    assert_eq!(ints, Tag::List(ListData {
+        element_type: TagType::Int,
+        elements: vec![Tag::Int(1), Tag::Int(2), Tag::Int(3)]
+    }));
+
+    assert!([Either(true), Either(true)][..].try_to_nbt().is_ok());
+
+    match [Either(true), Either(false)][..].try_to_nbt() {
+        Err(Error::Invalid) => (),
+        x                   => panic!("unexpected {:?}", x)
+    }
+
+    let mixed = ::std::panic::catch_unwind(|| [Either(true), Either(false)][..].to_nbt());
+    assert!(mixed.is_err());
+}
+
 /// Trait implementable by types that can be converted from NBT tags.
 pub trait FromNbt: Sized {
     fn from_nbt(val: &Tag) -> Option<Self>;
+
+    /// Like `from_nbt`, but integer types also accept `Float` and `Double`
+    /// tags holding an integral value that fits the type.
+    fn from_nbt_lenient(val: &Tag) -> Option<Self> {
+        Self::from_nbt(val)
+    }
+
+    // Convert an element of a `ByteArray`, which is stored as `u8`. Unsigned
+    // types take it as is, others see the signed byte NBT stores.
+    #[doc(hidden)]
+    fn from_nbt_byte_array_element(b: u8) -> Option<Self> {
+        Self::from_nbt(&Tag::Byte(b as i8))
+    }
 }
 
 macro_rules! fromnbt_impl {
@@ -98,19 +285,207 @@ macro_rules! fromnbt_impl {
     }
 }
 
+macro_rules! fromnbt_int_impl {
+        ($t:ty, $($p:path),+) => { impl FromNbt for $t {
+            fn from_nbt(val: &Tag) -> Option<Self> {
+                match *val {
+                    $(
+                        $p(ref x) => Some(x.clone() as $t)
+                    ),+,
+                    _         => None
+                }
+            }
+
+            fn from_nbt_lenient(val: &Tag) -> Option<Self> {
+                let x = match *val {
+                    Tag::Float(x)  => x as f64,
+                    Tag::Double(x) => x,
+                    _              => return Self::from_nbt(val)
+                };
+
+                // Exclusive upper bound, exactly representable as a float
+                let lim = (1_u64 << (::std::mem::size_of::<$t>() * 8 - 1)) as f64;
+
+                if x.fract() != 0.0 || x < -lim || x >= lim {
+                    None
+                } else {
+                    Some(x as $t)
+                }
+            }
+        }
+    }
+}
+
 // Allow smaller integer tags to be promoted to bigger types if need be.
 // It's a bit repetetive, but it works.
-fromnbt_impl!(i8, Tag::Byte);
-fromnbt_impl!(i16, Tag::Byte, Tag::Short);
-fromnbt_impl!(i32, Tag::Byte, Tag::Short, Tag::Int);
-fromnbt_impl!(i64, Tag::Byte, Tag::Short, Tag::Int, Tag::Long);
+fromnbt_int_impl!(i8, Tag::Byte);
+fromnbt_int_impl!(i16, Tag::Byte, Tag::Short);
+fromnbt_int_impl!(i32, Tag::Byte, Tag::Short, Tag::Int);
+fromnbt_int_impl!(i64, Tag::Byte, Tag::Short, Tag::Int, Tag::Long);
 fromnbt_impl!(f32, Tag::Float);
 fromnbt_impl!(f64, Tag::Float, Tag::Double);
 fromnbt_impl!(String, Tag::String);
 
+// Unsigned types accept any integer tag up to the one they're written as,
+// if the value is in range.
+macro_rules! fromnbt_unsigned_impl {
+        ($t:ty, $($p:path),+) => { impl FromNbt for $t {
+            fn from_nbt(val: &Tag) -> Option<Self> {
+                let x = match *val {
+                    $(
+                        $p(x) => x as i64
+                    ),+,
+                    _         => return None
+                };
+
+                if x < 0 || x > <$t>::max_value() as i64 {
+                    None
+                } else {
+                    Some(x as $t)
+                }
+            }
+
+            fn from_nbt_byte_array_element(b: u8) -> Option<Self> {
+                Some(b as $t)
+            }
+        }
+    }
+}
+
+fromnbt_unsigned_impl!(u8, Tag::Byte, Tag::Short);
+fromnbt_unsigned_impl!(u16, Tag::Byte, Tag::Short, Tag::Int);
+fromnbt_unsigned_impl!(u32, Tag::Byte, Tag::Short, Tag::Int, Tag::Long);
+
+/// `Tag::End` converts to `Some(None)`, any other tag to `Some(Some(x))` if
+/// `T` accepts it. For a missing compound entry, convert `Tag::End` or use
+/// `CompoundData::get_or` with a default of `None`.
+impl<T> FromNbt for Option<T>
+    where T: FromNbt {
+
+    fn from_nbt(val: &Tag) -> Option<Self> {
+        match *val {
+            Tag::End => Some(None),
+            _        => T::from_nbt(val).map(Some)
+        }
+    }
+}
+
+/// Any `Byte` other than 0 is true.
+impl FromNbt for bool {
+    fn from_nbt(val: &Tag) -> Option<Self> {
+        match *val {
+            Tag::Byte(x) => Some(x != 0),
+            _            => None
+        }
+    }
+}
+
+/// Lists convert element by element. Arrays convert as if they were lists of
+/// `Byte`, `Int` or `Long` tags.
+impl<T> FromNbt for Vec<T>
+    where T: FromNbt {
+
+    fn from_nbt(val: &Tag) -> Option<Self> {
+        match *val {
+            Tag::List(ref x)      => x.elements.iter().map(T::from_nbt).collect(),
+            Tag::ByteArray(ref x) => x.iter().map(|&v| T::from_nbt_byte_array_element(v)).collect(),
+            Tag::IntArray(ref x)  => x.iter().map(|&v| T::from_nbt(&Tag::Int(v))).collect(),
+            Tag::LongArray(ref x) => x.iter().map(|&v| T::from_nbt(&Tag::Long(v))).collect(),
+            _                     => None
+        }
+    }
+}
+
+impl<T> FromNbt for HashMap<String, T>
+    where T: FromNbt {
+
+    fn from_nbt(val: &Tag) -> Option<Self> {
+        match *val {
+            Tag::Compound(ref x) => x.elements.iter()
+                .map(|(k, v)| T::from_nbt(v).map(|v| (k.clone(), v)))
+                .collect(),
+            _ => None
+        }
+    }
+}
+
 #[test]
 fn test_fromnbt() {
     assert_eq!(FromNbt::from_nbt(&Tag::Short(12)), Some(12_i32));
     assert_eq!(FromNbt::from_nbt(&Tag::Byte(42_i8)), Some(42_i8));
-    assert!(<i8 as FromNbt>::from_nbt(&Tag::Int(42_i32)) == None);
+    assert!(<i8 as FromNbt>::from_nbt(&Tag::Int(42_i32)).is_none());
+}
+
+#[test]
+fn test_bool_unsigned() {
+    assert_eq!(true.to_nbt(), Tag::Byte(1));
+    assert_eq!(false.to_nbt(), Tag::Byte(0));
+    assert_eq!(bool::from_nbt(&Tag::Byte(0)), Some(false));
+    assert_eq!(bool::from_nbt(&Tag::Byte(-3)), Some(true));
+    assert_eq!(bool::from_nbt(&Tag::Int(1)), None);
+
+    assert_eq!(255_u8.to_nbt(), Tag::Short(255));
+    assert_eq!(65535_u16.to_nbt(), Tag::Int(65535));
+    assert_eq!(u32::MAX.to_nbt(), Tag::Long(0xFFFF_FFFF));
+
+    assert_eq!(u8::from_nbt(&255_u8.to_nbt()), Some(255));
+    assert_eq!(u8::from_nbt(&Tag::Short(256)), None);
+    assert_eq!(u8::from_nbt(&Tag::Byte(-1)), None);
+    assert_eq!(u16::from_nbt(&Tag::Long(1)), None);
+    assert_eq!(u32::from_nbt(&u32::MAX.to_nbt()), Some(u32::MAX));
+
+    // Byte array elements keep their stored value for unsigned types
+    let bytes = Tag::ByteArray(vec![0, 127, 128, 200, 255]);
+    assert_eq!(Vec::<u8>::from_nbt(&bytes), Some(vec![0, 127, 128, 200, 255]));
+    assert_eq!(Vec::<u16>::from_nbt(&bytes), Some(vec![0, 127, 128, 200, 255]));
+    assert_eq!(Vec::<i8>::from_nbt(&bytes), Some(vec![0, 127, -128, -56, -1]));
+}
+
+#[test]
+fn test_option() {
+    assert_eq!(Some(3_i32).to_nbt(), Tag::Int(3));
+    assert_eq!(None::<i32>.to_nbt(), Tag::End);
+    assert_eq!(Option::<i32>::from_nbt(&Tag::Int(3)), Some(Some(3)));
+    assert_eq!(Option::<i32>::from_nbt(&Tag::End), Some(None));
+    assert_eq!(Option::<i32>::from_nbt(&Tag::String("x".to_owned())), None);
+
+    let mut fields = HashMap::new();
+    fields.insert("CustomName".to_owned(), None);
+    fields.insert("id".to_owned(), Some("minecraft:pig".to_owned()));
+
+    let mut comp = CompoundData::new();
+    comp.insert("id".to_owned(), Tag::String("minecraft:pig".to_owned()));
+
+    assert_eq!(fields.to_nbt(), Tag::Compound(comp.clone()));
+    assert_eq!(comp.get_or::<Option<String>>("CustomName", None), None);
+}
+
+#[test]
+fn test_fromnbt_collections() {
+    let list = [1_i16, 2, 3][..].to_nbt();
+    assert_eq!(Vec::<i32>::from_nbt(&list), Some(vec![1, 2, 3]));
+    assert_eq!(Vec::<i8>::from_nbt(&list), None);
+    assert_eq!(Vec::<i64>::from_nbt(&Tag::IntArray(vec![-1, 7])), Some(vec![-1, 7]));
+    assert_eq!(Vec::<i8>::from_nbt(&Tag::ByteArray(vec![255, 1])), Some(vec![-1, 1]));
+    assert_eq!(Vec::<i32>::from_nbt(&Tag::Int(1)), None);
+
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), 1_i32);
+    map.insert("b".to_owned(), 2_i32);
+
+    assert_eq!(HashMap::<String, i32>::from_nbt(&map.to_nbt()), Some(map.clone()));
+    assert_eq!(HashMap::<String, String>::from_nbt(&map.to_nbt()), None);
+}
+
+#[test]
+fn test_fromnbt_lenient() {
+    assert_eq!(i32::from_nbt_lenient(&Tag::Double(5.0)), Some(5));
+    assert_eq!(i32::from_nbt_lenient(&Tag::Double(5.5)), None);
+    assert_eq!(i32::from_nbt(&Tag::Double(5.0)), None);
+    assert_eq!(i8::from_nbt_lenient(&Tag::Float(-128.0)), Some(-128));
+    assert_eq!(i8::from_nbt_lenient(&Tag::Float(128.0)), None);
+    assert_eq!(i64::from_nbt_lenient(&Tag::Double(9.3e18)), None);
+    assert_eq!(i64::from_nbt_lenient(&Tag::Double(f64::NAN)), None);
+    assert_eq!(i16::from_nbt_lenient(&Tag::Byte(7)), Some(7));
+    assert_eq!(f32::from_nbt_lenient(&Tag::Float(0.5)), Some(0.5));
 }
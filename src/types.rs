@@ -2,19 +2,52 @@
 
 use std;
 
-use std::ops::{Deref, DerefMut};
+use std::fmt;
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+use std::cmp::Ordering;
+use std::slice;
+use std::iter;
+use std::vec;
+use std::hash::{Hash, Hasher};
 use std::collections::HashMap;
+#[cfg(feature = "btreemap")]
+use std::collections::BTreeMap;
 
-use util::{IndexOpt, IndexOptMut};
+use util::{IndexOpt, IndexOptMut, ContentHasher};
+use traits::{ToNbt, FromNbt};
+use path;
+use mutf8;
 
 /// Compression flags
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Compression {
     /// Don't compress or uncompress.
     Uncompressed,
 
     /// Compress and uncompress using GZip.
-    GZip
+    GZip,
+
+    /// Write a GZip container holding uncompressed (stored) blocks, for
+    /// tools that expect the GZip magic. Reads the same as `GZip`.
+    GZipStored,
+
+    /// Compress and uncompress using Zlib, as used for most chunks in region
+    /// files.
+    Zlib,
+
+    /// Detect the compression from the first bytes of the data when reading.
+    /// Writes uncompressed data.
+    Auto
+}
+
+/// Byte order of the numbers in encoded NBT data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    /// Most significant byte first, as used by Java Edition.
+    Big,
+
+    /// Least significant byte first, as used by Bedrock Edition.
+    Little
 }
 
 /// Things that can go wrong when reading or writing NBT tags.
@@ -29,8 +62,32 @@ pub enum Error {
     /// Tried to serialize an invalid NBT structure.
     Invalid,
 
+    /// The input ended in the middle of a tag.
+    UnexpectedEof,
+
+    /// Decoding took longer than the decoder's deadline allowed.
+    TimedOut,
+
     /// An IO error happened while decoding or encoding an NBT Tag.
-    IOError(std::io::Error)
+    IOError(std::io::Error),
+
+    /// There is no tag at the given path, or the path is malformed.
+    PathNotFound(String),
+
+    /// The value at the given path isn't allowed by a schema.
+    SchemaViolation(String),
+
+    /// A decoder limit was exceeded by the value at the given path.
+    LimitExceeded(String),
+
+    /// A string at the given path can't be encoded as an NBT string.
+    InvalidString(String),
+
+    /// Invalid SNBT input, with the byte offset where the problem was found.
+    Syntax { offset: usize, message: String },
+
+    /// A value couldn't be converted to or from a tag with serde.
+    Serde(String)
 }
 
 impl From<std::io::Error> for Error {
@@ -39,8 +96,30 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::EndOfCompound          => write!(f, "unexpected end of compound"),
+            Error::Malformed              => write!(f, "malformed NBT data"),
+            Error::Invalid                => write!(f, "invalid NBT structure"),
+            Error::UnexpectedEof          => write!(f, "unexpected end of input"),
+            Error::TimedOut               => write!(f, "decoding timed out"),
+            Error::IOError(ref e)         => write!(f, "IO error: {}", e),
+            Error::PathNotFound(ref p)    => write!(f, "no tag at path {:?}", p),
+            Error::SchemaViolation(ref p) => write!(f, "schema violation at {:?}", p),
+            Error::LimitExceeded(ref p)   => write!(f, "limit exceeded at {:?}", p),
+            Error::InvalidString(ref p)   => write!(f, "string at {:?} can't be encoded", p),
+            Error::Syntax { offset, ref message } =>
+                write!(f, "syntax error at offset {}: {}", offset, message),
+            Error::Serde(ref m) => write!(f, "{}", m)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 /// Possible NBT tag types.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TagType {
     /// End marker
     End,
@@ -90,6 +169,20 @@ impl TagType {
         }
     }
 
+    /// Return the encoded size of a value of this type, if it's a fixed
+    /// width primitive.
+    pub fn fixed_size(&self) -> Option<usize> {
+        match *self {
+            TagType::Byte   => Some(1),
+            TagType::Short  => Some(2),
+            TagType::Int    => Some(4),
+            TagType::Long   => Some(8),
+            TagType::Float  => Some(4),
+            TagType::Double => Some(8),
+            _               => None
+        }
+    }
+
     pub fn to_binary(&self) -> u8 {
         match *self {
             TagType::End       => 0,
@@ -111,16 +204,78 @@ impl TagType {
 
 
 /// The internal representation of a list
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ListData {
     pub element_type: TagType,
     pub elements: Vec<Tag>
 }
 
+/// Iterator over a list's elements converted to `T`, created by
+/// `ListData::iter_as`.
+pub type IterAs<'a, T> = iter::Map<slice::Iter<'a, Tag>, fn(&Tag) -> Option<T>>;
+
+impl ListData {
+    /// Create an empty list declared to hold elements of type `element_type`.
+    ///
+    /// The declared type is written out even while the list is empty, so
+    /// readers know what to expect.
+    pub fn new(element_type: TagType) -> ListData {
+        ListData {
+            element_type,
+            elements: Vec::new()
+        }
+    }
+
+    /// Iterate over the elements in batches of `size`, the last of which may
+    /// be shorter. Panics if `size` is 0.
+    pub fn chunks(&self, size: usize) -> slice::Chunks<'_, Tag> {
+        self.elements.chunks(size)
+    }
+
+    /// Iterate over the elements converted to `T`, yielding `None` for
+    /// elements that can't be converted.
+    pub fn iter_as<T: FromNbt>(&self) -> IterAs<'_, T> {
+        self.elements.iter().map(T::from_nbt as fn(&Tag) -> Option<T>)
+    }
+
+    /// Group the values of a list of compounds by key.
+    ///
+    /// Every element must be a compound with the same set of keys, so that
+    /// each column has one entry per element, in list order. Returns `None`
+    /// otherwise.
+    pub fn to_columns(&self) -> Option<HashMap<String, Vec<&Tag>>> {
+        let mut columns: HashMap<String, Vec<&Tag>> = HashMap::new();
+
+        for (i, e) in self.elements.iter().enumerate() {
+            let c = match *e {
+                Tag::Compound(ref c) => c,
+                _                    => return None
+            };
+
+            if i > 0 && c.elements.len() != columns.len() {
+                return None;
+            }
+
+            for (k, v) in c.elements.iter() {
+                if i == 0 {
+                    columns.insert(k.clone(), Vec::with_capacity(self.elements.len()));
+                }
+
+                match columns.get_mut(k) {
+                    Some(col) => col.push(v),
+                    None      => return None
+                }
+            }
+        }
+
+        Some(columns)
+    }
+}
+
 impl IndexOpt<usize> for ListData {
     type Output = Tag;
 
-    fn index_opt<'a>(&'a self, i: usize) -> Option<&'a Tag> {
+    fn index_opt(&self, i: usize) -> Option<&Tag> {
         if i >= self.elements.len() {
             None
         } else {
@@ -130,7 +285,7 @@ impl IndexOpt<usize> for ListData {
 }
 
 impl IndexOptMut<usize> for ListData {
-    fn index_opt_mut<'a>(&'a mut self, i: usize) -> Option<&'a mut Tag> {
+    fn index_opt_mut(&mut self, i: usize) -> Option<&mut Tag> {
         if i >= self.elements.len() {
             None
         } else {
@@ -140,25 +295,91 @@ impl IndexOptMut<usize> for ListData {
 }
 
 
+impl Hash for ListData {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.element_type.hash(state);
+        self.elements.hash(state);
+    }
+}
+
 impl Deref for ListData {
     type Target = Vec<Tag>;
 
-    fn deref<'a>(&'a self) -> &'a Self::Target {
+    fn deref(&self) -> &Self::Target {
         &self.elements
     }
 }
 
 impl DerefMut for ListData {
-    fn deref_mut<'a>(&'a mut self) -> &'a mut Self::Target {
+    fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.elements
     }
 }
 
 
+/// The map type backing compounds.
+///
+/// This is a `HashMap` by default. With the `btreemap` feature enabled it's a
+/// `BTreeMap` instead, which iterates in key order and isn't susceptible to
+/// hash flooding from untrusted input.
+#[cfg(not(feature = "btreemap"))]
+pub type Map = HashMap<String, Tag>;
+
+/// The map type backing compounds.
+///
+/// This is a `HashMap` by default. With the `btreemap` feature enabled it's a
+/// `BTreeMap` instead, which iterates in key order and isn't susceptible to
+/// hash flooding from untrusted input.
+#[cfg(feature = "btreemap")]
+pub type Map = BTreeMap<String, Tag>;
+
 /// The internal representation of a compound
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct CompoundData {
-    pub elements: HashMap<String, Tag>
+    pub elements: Map
+}
+
+impl CompoundData {
+    /// Create an empty compound.
+    pub fn new() -> CompoundData {
+        CompoundData { elements: Map::new() }
+    }
+
+    /// Look up `key` and convert it to `T`, falling back to `default` if the
+    /// key is absent or holds a tag that can't be converted.
+    pub fn get_or<T: FromNbt>(&self, key: &str, default: T) -> T {
+        self.elements.get(key).and_then(T::from_nbt).unwrap_or(default)
+    }
+
+    /// Iterate over the entries in sorted key order, for output that doesn't
+    /// depend on the map's iteration order.
+    pub fn iter_sorted(&self) -> vec::IntoIter<(&str, &Tag)> {
+        let mut entries: Vec<(&str, &Tag)> = self.elements.iter()
+            .map(|(k, v)| (k.as_str(), v))
+            .collect();
+
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+}
+
+/// Entries are combined independently of their order, so equal compounds
+/// hash equally whatever order their map iterates in.
+impl Hash for CompoundData {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut sum = 0_u64;
+
+        for (k, v) in self.elements.iter() {
+            let mut h = ContentHasher::new();
+            k.hash(&mut h);
+            v.hash(&mut h);
+
+            sum = sum.wrapping_add(h.finish());
+        }
+
+        state.write_usize(self.elements.len());
+        state.write_u64(sum);
+    }
 }
 
 impl<'a> IndexOpt<&'a str> for CompoundData {
@@ -177,20 +398,133 @@ impl<'a> IndexOptMut<&'a str> for CompoundData {
 
 
 impl Deref for CompoundData {
-    type Target = HashMap<String, Tag>;
+    type Target = Map;
 
-    fn deref<'a>(&'a self) -> &'a Self::Target {
+    fn deref(&self) -> &Self::Target {
         &self.elements
     }
 }
 
 impl DerefMut for CompoundData {
-    fn deref_mut<'a>(&'a mut self) -> &'a mut Self::Target {
+    fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.elements
     }
 }
 
 
+/// Build a compound by chaining setters.
+///
+/// Each setter inserts a value under a key, replacing any previous value
+/// for the key.
+#[derive(Debug, Clone, Default)]
+pub struct CompoundBuilder {
+    data: CompoundData
+}
+
+impl CompoundBuilder {
+    /// Create a builder for an empty compound.
+    pub fn new() -> CompoundBuilder {
+        CompoundBuilder { data: CompoundData::new() }
+    }
+
+    /// Insert `value` converted with `ToNbt`. Values that convert to
+    /// `Tag::End`, like `None`, are left out.
+    pub fn set<T: ToNbt>(self, key: &str, value: T) -> CompoundBuilder {
+        match value.to_nbt() {
+            Tag::End => self,
+            t        => self.insert(key, t)
+        }
+    }
+
+    /// Insert a `Byte`.
+    pub fn byte(self, key: &str, value: i8) -> CompoundBuilder {
+        self.insert(key, Tag::Byte(value))
+    }
+
+    /// Insert a `Short`.
+    pub fn short(self, key: &str, value: i16) -> CompoundBuilder {
+        self.insert(key, Tag::Short(value))
+    }
+
+    /// Insert an `Int`.
+    pub fn int(self, key: &str, value: i32) -> CompoundBuilder {
+        self.insert(key, Tag::Int(value))
+    }
+
+    /// Insert a `Long`.
+    pub fn long(self, key: &str, value: i64) -> CompoundBuilder {
+        self.insert(key, Tag::Long(value))
+    }
+
+    /// Insert a `Float`.
+    pub fn float(self, key: &str, value: f32) -> CompoundBuilder {
+        self.insert(key, Tag::Float(value))
+    }
+
+    /// Insert a `Double`.
+    pub fn double(self, key: &str, value: f64) -> CompoundBuilder {
+        self.insert(key, Tag::Double(value))
+    }
+
+    /// Insert a `String`.
+    pub fn string(self, key: &str, value: &str) -> CompoundBuilder {
+        self.insert(key, Tag::String(value.to_owned()))
+    }
+
+    /// Insert a `List`.
+    pub fn list(self, key: &str, value: ListData) -> CompoundBuilder {
+        self.insert(key, Tag::List(value))
+    }
+
+    /// Insert a `Compound`, like one made by another builder.
+    pub fn compound(self, key: &str, value: CompoundData) -> CompoundBuilder {
+        self.insert(key, Tag::Compound(value))
+    }
+
+    fn insert(mut self, key: &str, value: Tag) -> CompoundBuilder {
+        self.data.elements.insert(key.to_owned(), value);
+        self
+    }
+
+    /// Return the compound's data.
+    pub fn build_data(self) -> CompoundData {
+        self.data
+    }
+
+    /// Return the compound as a tag.
+    pub fn build(self) -> Tag {
+        Tag::Compound(self.data)
+    }
+}
+
+#[test]
+fn test_compound_builder() {
+    let mut items = ListData::new(TagType::Compound);
+    items.elements.push(CompoundBuilder::new().string("id", "minecraft:apple").byte("Count", 3).build());
+
+    let player = CompoundBuilder::new()
+        .byte("X", 1)
+        .string("Name", "Steve")
+        .list("Items", items.clone())
+        .compound("Abilities", CompoundBuilder::new().set("flying", true).build_data())
+        .set("Score", 10_i64)
+        .set("CustomName", None::<String>)
+        .build();
+
+    let mut abilities = CompoundData::new();
+    abilities.insert("flying".to_owned(), Tag::Byte(1));
+
+    let mut expected = CompoundData::new();
+    expected.insert("X".to_owned(), Tag::Byte(1));
+    expected.insert("Name".to_owned(), Tag::String("Steve".to_owned()));
+    expected.insert("Items".to_owned(), Tag::List(items));
+    expected.insert("Abilities".to_owned(), Tag::Compound(abilities));
+    expected.insert("Score".to_owned(), Tag::Long(10));
+
+    assert_eq!(player, Tag::Compound(expected));
+}
+
+
 #[test]
 fn test_aggregate() {
     let list = ListData {
@@ -202,9 +536,7 @@ fn test_aggregate() {
     assert_eq!(list.index_opt(5), None);
 
 
-    let mut comp = CompoundData {
-        elements: HashMap::new()
-    };
+    let mut comp = CompoundData::new();
 
     comp.insert("Foo".to_owned(), Tag::String("Bar".to_owned()));
     comp.insert("Bar".to_owned(), Tag::Short(42));
@@ -212,8 +544,285 @@ fn test_aggregate() {
     assert_eq!(comp.index_opt("Foo"), Some(&Tag::String("Bar".to_owned())));
 }
 
+#[test]
+fn test_to_columns() {
+    let entity = |id: &str, x: f64| {
+        let mut c = CompoundData::new();
+        c.insert("id".to_owned(), Tag::String(id.to_owned()));
+        c.insert("x".to_owned(), Tag::Double(x));
+        Tag::Compound(c)
+    };
+
+    let list = ListData {
+        element_type: TagType::Compound,
+        elements: vec![entity("cow", 1.0), entity("pig", 2.0)]
+    };
+
+    let cols = list.to_columns().unwrap();
+
+    assert_eq!(cols.len(), 2);
+    assert_eq!(cols["x"], vec![&Tag::Double(1.0), &Tag::Double(2.0)]);
+    assert_eq!(cols["id"][1], &Tag::String("pig".to_owned()));
+
+    let shorts = ListData {
+        element_type: TagType::Short,
+        elements: vec![Tag::Short(1)]
+    };
+
+    assert_eq!(shorts.to_columns(), None);
+}
+
+#[test]
+fn test_validate_strings() {
+    let mut inner = CompoundData::new();
+    inner.insert("ok".to_owned(), Tag::String("\u{0}\u{1F600}".to_owned()));
+
+    let mut root = CompoundData::new();
+    root.insert("Sign".to_owned(), Tag::List(ListData {
+        element_type: TagType::Compound,
+        elements: vec![Tag::Compound(inner)]
+    }));
+
+    let mut root = Tag::Compound(root);
+    assert!(root.validate_strings().is_ok());
+
+    // 11000 three-byte characters don't fit the 16 bit length prefix
+    let long = "\u{20AC}".repeat(11000);
+
+    if let Tag::Compound(ref mut x) = root {
+        if let Some(&mut Tag::List(ref mut l)) = x.get_mut("Sign") {
+            if let Tag::Compound(ref mut c) = l[0] {
+                c.insert("Text".to_owned(), Tag::String(long));
+            }
+        }
+    }
+
+    match root.validate_strings() {
+        Err(Error::InvalidString(p)) => assert_eq!(p, "Sign[0].Text"),
+        x                            => panic!("unexpected {:?}", x)
+    }
+}
+
+#[test]
+fn test_find_inconsistent_lists() {
+    let mut good = ListData::new(TagType::Int);
+    good.elements.push(Tag::Int(1));
+
+    let mut bad = ListData::new(TagType::Int);
+    bad.elements.push(Tag::Int(1));
+    bad.elements.push(Tag::Short(2));
+
+    let mut nested = ListData::new(TagType::List);
+    nested.elements.push(Tag::List(good.clone()));
+    nested.elements.push(Tag::List(bad));
+
+    let mut c = CompoundData::new();
+    c.insert("Good".to_owned(), Tag::List(good));
+    c.insert("Nested".to_owned(), Tag::List(nested));
+    let tag = Tag::Compound(c);
+
+    assert_eq!(tag.find_inconsistent_lists(), vec!["Nested[1]".to_owned()]);
+    assert!(Tag::Int(1).find_inconsistent_lists().is_empty());
+}
+
+#[test]
+fn test_unsigned_views() {
+    assert_eq!(Tag::Int(-1).as_u32(), Some(0xFFFFFFFF));
+    assert_eq!(Tag::Int(7).as_u32(), Some(7));
+    assert_eq!(Tag::Long(i64::MIN).as_u64(), Some(1 << 63));
+    assert_eq!(Tag::Long(1).as_u32(), None);
+}
+
+#[test]
+fn test_array_views() {
+    let mut longs = Tag::LongArray(vec![1, 2, 3]);
+
+    assert_eq!(longs.as_long_array(), Some(&[1_i64, 2, 3][..]));
+    assert_eq!(longs.as_int_array(), None);
+
+    longs.as_long_array_mut().unwrap()[1] = -2;
+    assert_eq!(longs, Tag::LongArray(vec![1, -2, 3]));
+
+    let mut bytes = Tag::ByteArray(vec![0xFF]);
+    bytes.as_byte_array_mut().unwrap()[0] = 7;
+    assert_eq!(bytes.as_byte_array(), Some(&[7_u8][..]));
+    assert_eq!(Tag::Int(1).as_int_array_mut(), None);
+}
+
+#[test]
+fn test_byte_array_signed() {
+    assert_eq!(Tag::ByteArray(vec![0, 0x7F, 0x80, 0xFF]).byte_array_signed(), Some(vec![0, 127, -128, -1]));
+    assert_eq!(Tag::Byte(-1).byte_array_signed(), None);
+}
+
+#[test]
+fn test_array_push() {
+    let mut ints = Tag::IntArray(vec![1]);
+
+    ints.array_push(2).unwrap();
+    assert_eq!(ints, Tag::IntArray(vec![1, 2]));
+    assert!(ints.array_push(1 << 40).is_err());
+
+    let mut bytes = Tag::ByteArray(vec![]);
+    bytes.array_push(-1).unwrap();
+    bytes.array_push(200).unwrap();
+    assert_eq!(bytes, Tag::ByteArray(vec![0xFF, 200]));
+    assert!(bytes.array_push(256).is_err());
+
+    assert!(Tag::Int(1).array_push(2).is_err());
+}
+
+#[test]
+fn test_count_nodes() {
+    let mut pos = CompoundData::new();
+    pos.insert("x".to_owned(), Tag::Int(1));
+    pos.insert("y".to_owned(), Tag::Int(2));
+
+    let mut root = CompoundData::new();
+    root.insert("Pos".to_owned(), Tag::Compound(pos));
+    root.insert("Ids".to_owned(), Tag::List(ListData {
+        element_type: TagType::Short,
+        elements: vec![Tag::Short(1), Tag::Short(2), Tag::Short(3)]
+    }));
+    root.insert("Data".to_owned(), Tag::IntArray(vec![1, 2, 3]));
+
+    // root, Pos, x, y, Ids, 3 shorts, Data
+    assert_eq!(Tag::Compound(root).count_nodes(), 9);
+    assert_eq!(Tag::Byte(1).count_nodes(), 1);
+}
+
+#[test]
+fn test_get_or() {
+    let mut comp = CompoundData::new();
+    comp.insert("Health".to_owned(), Tag::Short(12));
+    comp.insert("Name".to_owned(), Tag::String("Steve".to_owned()));
+
+    assert_eq!(comp.get_or("Health", 20_i16), 12);
+    assert_eq!(comp.get_or("Hunger", 20_i16), 20);
+    assert_eq!(comp.get_or("Name", 20_i16), 20);
+}
+
+#[test]
+fn test_encoded_len() {
+    use encode;
+
+    let mut strings = ListData::new(TagType::String);
+    strings.elements.extend(vec![Tag::String("nul\0".to_owned()), Tag::String("\u{1F600}".to_owned())]);
+
+    let mut c = CompoundData::new();
+    c.insert("caf\u{e9}".to_owned(), Tag::List(strings));
+    c.insert("empty".to_owned(), Tag::List(ListData::new(TagType::Int)));
+    c.insert("none".to_owned(), Tag::Compound(CompoundData::new()));
+
+    let tags = vec![
+        Tag::Byte(1), Tag::Short(2), Tag::Int(3), Tag::Long(4), Tag::Float(5.0), Tag::Double(6.0),
+        Tag::String("".to_owned()), Tag::String("\0\u{10000}".to_owned()),
+        Tag::ByteArray(vec![1, 2]), Tag::IntArray(vec![3]), Tag::LongArray(vec![]),
+        Tag::List(ListData::new(TagType::End)),
+        Tag::Compound(c)
+    ];
+
+    for t in &tags {
+        for name in &["", "n\0me"] {
+            assert_eq!(t.named_encoded_len(name), encode::to_bytes(name, t).unwrap().len());
+        }
+    }
+
+    assert_eq!(Tag::List(ListData::new(TagType::Compound)).encoded_len(), 5);
+}
+
+#[test]
+fn test_clone_to_depth() {
+    use snbt::parse_snbt;
+
+    let deep = parse_snbt("{a: {b: {c: {d: 1}}, n: 5}, l: [[1, 2]], s: \"x\"}").unwrap();
+
+    assert_eq!(deep.clone_to_depth(2), parse_snbt("{a: {b: {c: {}}, n: 5}, l: [[1, 2]], s: \"x\"}").unwrap());
+    assert_eq!(deep.clone_to_depth(10), deep);
+
+    let shallow = deep.clone_to_depth(0);
+
+    assert_eq!(shallow.get_path("a"), Some(&Tag::Compound(CompoundData::new())));
+    assert_eq!(shallow.get_path("l"), Some(&Tag::List(ListData::new(TagType::List))));
+    assert_eq!(shallow.get_path("s"), deep.get_path("s"));
+}
+
+#[test]
+fn test_iter_sorted() {
+    let tag = nbt!({ "b": 2, "c": 3, "a": 1 });
+
+    let c = match tag {
+        Tag::Compound(ref c) => c,
+        _                    => panic!("expected a compound")
+    };
+
+    let entries: Vec<_> = c.iter_sorted().collect();
+    assert_eq!(entries, [("a", &Tag::Int(1)), ("b", &Tag::Int(2)), ("c", &Tag::Int(3))]);
+}
+
+#[test]
+fn test_iter_as() {
+    let mut list = ListData::new(TagType::Int);
+    list.elements.extend(vec![Tag::Int(1), Tag::String("x".to_owned()), Tag::Int(3)]);
+
+    let ints: Vec<Option<i32>> = list.iter_as().collect();
+    assert_eq!(ints, [Some(1), None, Some(3)]);
+}
+
+#[test]
+fn test_list_chunks() {
+    let mut list = ListData::new(TagType::Int);
+    list.elements.extend((0 .. 10).map(Tag::Int));
+
+    let sizes: Vec<usize> = list.chunks(3).map(|c| c.len()).collect();
+    assert_eq!(sizes, [3, 3, 3, 1]);
+    assert_eq!(list.chunks(3).last(), Some(&[Tag::Int(9)][..]));
+}
+
+#[test]
+fn test_hash() {
+    use std::collections::hash_map::DefaultHasher;
+    use snbt::parse_snbt;
+
+    let hash = |t: &Tag| {
+        let mut h = DefaultHasher::new();
+        t.hash(&mut h);
+        h.finish()
+    };
+
+    let a = parse_snbt("{Pos: [0.0d, 64.0d], Name: \"Steve\", Tags: {x: 1b, y: 2b}}").unwrap();
+    let b = parse_snbt("{Tags: {y: 2b, x: 1b}, Name: \"Steve\", Pos: [-0.0d, 64.0d]}").unwrap();
+    let c = parse_snbt("{Tags: {y: 1b, x: 2b}, Name: \"Steve\", Pos: [0.0d, 64.0d]}").unwrap();
+
+    assert_eq!(a, b);
+    assert_eq!(hash(&a), hash(&b));
+    assert_eq!(hash(&a), hash(&a.clone()));
+    assert!(hash(&a) != hash(&c));
+    assert!(hash(&Tag::Int(1)) != hash(&Tag::Long(1)));
+}
+
+#[test]
+fn test_approx_eq() {
+    let pos = |x: f64| {
+        let mut comp = CompoundData::new();
+        comp.insert("Pos".to_owned(), Tag::List(ListData {
+            element_type: TagType::Double,
+            elements: vec![Tag::Double(x), Tag::Double(64.0)]
+        }));
+        comp.insert("id".to_owned(), Tag::String("minecraft:pig".to_owned()));
+        Tag::Compound(comp)
+    };
+
+    assert!(pos(0.1 + 0.2).approx_eq(&pos(0.3), 1e-9));
+    assert!(pos(0.1 + 0.2) != pos(0.3));
+    assert!(!pos(0.31).approx_eq(&pos(0.3), 1e-9));
+    assert!(Tag::Float(1.0).approx_eq(&Tag::Float(1.0000001), 1e-6));
+    assert!(!Tag::Float(1.0).approx_eq(&Tag::Double(1.0), 1.0));
+    assert!(!Tag::Int(1).approx_eq(&Tag::Int(2), 10.0));
+}
+
 /// An NBT value type.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Tag {
     /// End marker.
     End,
@@ -232,7 +841,12 @@ pub enum Tag {
     Double(f64),
     /// UTF-8 string.
     String(String),
-    /// Vector of unsigned 8 bit integers.
+    /// Vector of 8 bit integers.
+    ///
+    /// NBT bytes are signed, but these are stored as `u8` since byte arrays
+    /// usually hold raw data such as nibble arrays. Each element keeps the
+    /// bit pattern of the signed byte it was read from, so `-1` is stored as
+    /// `255`; use `byte_array_signed` for the signed values.
     ByteArray(Vec<u8>),
     /// Vector of signed 32 bit integers.
     IntArray(Vec<i32>),
@@ -244,6 +858,129 @@ pub enum Tag {
     Compound(CompoundData)
 }
 
+/// Floats are hashed by their bits, with both zeros hashing the same since
+/// they compare equal. `Tag` still has no `Eq` impl, as NaNs don't compare
+/// equal to themselves.
+impl Hash for Tag {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get_type().hash(state);
+
+        match *self {
+            Tag::End              => (),
+            Tag::Byte(x)          => x.hash(state),
+            Tag::Short(x)         => x.hash(state),
+            Tag::Int(x)           => x.hash(state),
+            Tag::Long(x)          => x.hash(state),
+            Tag::Float(x)         => (if x == 0.0 { 0 } else { x.to_bits() }).hash(state),
+            Tag::Double(x)        => (if x == 0.0 { 0 } else { x.to_bits() }).hash(state),
+            Tag::String(ref x)    => x.hash(state),
+            Tag::ByteArray(ref x) => x.hash(state),
+            Tag::IntArray(ref x)  => x.hash(state),
+            Tag::LongArray(ref x) => x.hash(state),
+            Tag::List(ref x)      => x.hash(state),
+            Tag::Compound(ref x)  => x.hash(state)
+        }
+    }
+}
+
+/// Look up a key of a compound, returning `None` for other tags.
+impl<'a> IndexOpt<&'a str> for Tag {
+    type Output = Tag;
+
+    fn index_opt<'b>(&'b self, i: &'a str) -> Option<&'b Tag> {
+        match *self {
+            Tag::Compound(ref x) => x.index_opt(i),
+            _                    => None
+        }
+    }
+}
+
+impl<'a> IndexOptMut<&'a str> for Tag {
+    fn index_opt_mut<'b>(&'b mut self, i: &'a str) -> Option<&'b mut Tag> {
+        match *self {
+            Tag::Compound(ref mut x) => x.index_opt_mut(i),
+            _                        => None
+        }
+    }
+}
+
+/// Look up an element of a list, returning `None` for other tags.
+impl IndexOpt<usize> for Tag {
+    type Output = Tag;
+
+    fn index_opt(&self, i: usize) -> Option<&Tag> {
+        match *self {
+            Tag::List(ref x) => x.index_opt(i),
+            _                => None
+        }
+    }
+}
+
+impl IndexOptMut<usize> for Tag {
+    fn index_opt_mut(&mut self, i: usize) -> Option<&mut Tag> {
+        match *self {
+            Tag::List(ref mut x) => x.index_opt_mut(i),
+            _                    => None
+        }
+    }
+}
+
+/// Look up a key of a compound.
+///
+/// # Panics
+///
+/// Panics if the tag isn't a compound or has no such key. Use `index_opt`
+/// to handle those cases.
+impl<'a> Index<&'a str> for Tag {
+    type Output = Tag;
+
+    fn index(&self, i: &'a str) -> &Tag {
+        match self.index_opt(i) {
+            Some(x) => x,
+            None    => panic!("no compound entry {:?} in {:?} tag", i, self.get_type())
+        }
+    }
+}
+
+impl<'a> IndexMut<&'a str> for Tag {
+    fn index_mut(&mut self, i: &'a str) -> &mut Tag {
+        let t = self.get_type();
+
+        match self.index_opt_mut(i) {
+            Some(x) => x,
+            None    => panic!("no compound entry {:?} in {:?} tag", i, t)
+        }
+    }
+}
+
+/// Look up an element of a list.
+///
+/// # Panics
+///
+/// Panics if the tag isn't a list or the index is out of bounds. Use
+/// `index_opt` to handle those cases.
+impl Index<usize> for Tag {
+    type Output = Tag;
+
+    fn index(&self, i: usize) -> &Tag {
+        match self.index_opt(i) {
+            Some(x) => x,
+            None    => panic!("no list element {} in {:?} tag", i, self.get_type())
+        }
+    }
+}
+
+impl IndexMut<usize> for Tag {
+    fn index_mut(&mut self, i: usize) -> &mut Tag {
+        let t = self.get_type();
+
+        match self.index_opt_mut(i) {
+            Some(x) => x,
+            None    => panic!("no list element {} in {:?} tag", i, t)
+        }
+    }
+}
+
 impl Tag {
     /// Return the tag's type.
     pub fn get_type(&self) -> TagType {
@@ -263,7 +1000,437 @@ impl Tag {
             Tag::Compound(_)  => TagType::Compound
         }
     }
+
+    /// Reinterpret the bits of an `Int` tag as an unsigned integer.
+    ///
+    /// This is not a range check: `Int(-1)` becomes `0xFFFFFFFF`. Use it for
+    /// fields known to hold unsigned values in a signed tag.
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Tag::Int(x) => Some(x as u32),
+            _           => None
+        }
+    }
+
+    /// Reinterpret the bits of a `Long` tag as an unsigned integer.
+    ///
+    /// Like `as_u32`, this is a bit reinterpretation, not a range check.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Tag::Long(x) => Some(x as u64),
+            _            => None
+        }
+    }
+
+    /// Return the contents of a `ByteArray` tag.
+    pub fn as_byte_array(&self) -> Option<&[u8]> {
+        match *self {
+            Tag::ByteArray(ref x) => Some(x),
+            _                     => None
+        }
+    }
+
+    /// Return the contents of a `ByteArray` tag as the signed bytes NBT
+    /// stores, reinterpreting each element's bits.
+    pub fn byte_array_signed(&self) -> Option<Vec<i8>> {
+        self.as_byte_array().map(|x| x.iter().map(|&b| b as i8).collect())
+    }
+
+    /// Return the contents of a `ByteArray` tag for modification.
+    pub fn as_byte_array_mut(&mut self) -> Option<&mut [u8]> {
+        match *self {
+            Tag::ByteArray(ref mut x) => Some(x),
+            _                         => None
+        }
+    }
+
+    /// Return the contents of an `IntArray` tag.
+    pub fn as_int_array(&self) -> Option<&[i32]> {
+        match *self {
+            Tag::IntArray(ref x) => Some(x),
+            _                    => None
+        }
+    }
+
+    /// Return the contents of an `IntArray` tag for modification.
+    pub fn as_int_array_mut(&mut self) -> Option<&mut [i32]> {
+        match *self {
+            Tag::IntArray(ref mut x) => Some(x),
+            _                        => None
+        }
+    }
+
+    /// Return the contents of a `LongArray` tag.
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match *self {
+            Tag::LongArray(ref x) => Some(x),
+            _                     => None
+        }
+    }
+
+    /// Return the contents of a `LongArray` tag for modification.
+    pub fn as_long_array_mut(&mut self) -> Option<&mut [i64]> {
+        match *self {
+            Tag::LongArray(ref mut x) => Some(x),
+            _                         => None
+        }
+    }
+
+    /// Append `value` to an array tag, narrowed to the array's element type.
+    ///
+    /// Fails with `Error::Invalid` if the tag isn't an array, or if the value
+    /// doesn't fit the element type. Byte arrays accept both the signed and
+    /// the unsigned range, from -128 to 255.
+    pub fn array_push(&mut self, value: i64) -> Result<()> {
+        match *self {
+            Tag::ByteArray(ref mut x) if (-128 ..= 255).contains(&value) =>
+                x.push(value as u8),
+
+            Tag::IntArray(ref mut x)
+                if (i32::MIN as i64 ..= i32::MAX as i64).contains(&value) =>
+                x.push(value as i32),
+
+            Tag::LongArray(ref mut x) => x.push(value),
+
+            _ => return Err(Error::Invalid)
+        }
+
+        Ok(())
+    }
+
+    /// Return the number of tags in the tree, including the tag itself and
+    /// all containers.
+    pub fn count_nodes(&self) -> usize {
+        1 + match *self {
+            Tag::List(ref x)     => x.elements.iter().map(|e| e.count_nodes()).sum(),
+            Tag::Compound(ref x) => x.elements.values().map(|v| v.count_nodes()).sum(),
+            _                    => 0
+        }
+    }
+
+    /// Clone the tag down to `depth` levels below it, for previews of large
+    /// trees.
+    ///
+    /// Lists and compounds nested deeper than that are replaced by empty
+    /// ones of the same kind, with lists keeping their element type, so the
+    /// result can still be encoded. Other values are cloned in full.
+    pub fn clone_to_depth(&self, depth: usize) -> Tag {
+        fn child(t: &Tag, depth: usize) -> Tag {
+            match *t {
+                Tag::List(ref x) if depth == 0 => Tag::List(ListData::new(x.element_type)),
+                Tag::Compound(_) if depth == 0 => Tag::Compound(CompoundData::new()),
+                _                              => t.clone_to_depth(depth.saturating_sub(1))
+            }
+        }
+
+        match *self {
+            Tag::List(ref x) => Tag::List(ListData {
+                element_type: x.element_type,
+                elements: x.elements.iter().map(|e| child(e, depth)).collect()
+            }),
+
+            Tag::Compound(ref x) => Tag::Compound(CompoundData {
+                elements: x.elements.iter().map(|(k, v)| (k.clone(), child(v, depth))).collect()
+            }),
+
+            _ => self.clone()
+        }
+    }
+
+    /// Compare two tags by a total order: first by type, then by value.
+    ///
+    /// Floats are ordered as by `f64::total_cmp`, lists and arrays element
+    /// by element, and compounds by their entries in key order. `Tag` has no
+    /// `Ord` impl since its float `PartialEq` isn't a total equality.
+    pub fn total_cmp(&self, other: &Tag) -> Ordering {
+        fn seq<'a, I: Iterator<Item = &'a Tag>>(mut a: I, mut b: I) -> Ordering {
+            loop {
+                match (a.next(), b.next()) {
+                    (Some(x), Some(y)) => match x.total_cmp(y) {
+                        Ordering::Equal => continue,
+                        o               => return o
+                    },
+                    (x, y) => return x.is_some().cmp(&y.is_some())
+                }
+            }
+        }
+
+        fn entries(c: &CompoundData) -> Vec<(&String, &Tag)> {
+            let mut e: Vec<_> = c.elements.iter().collect();
+            e.sort_by(|a, b| a.0.cmp(b.0));
+            e
+        }
+
+        match (self, other) {
+            (&Tag::Byte(a), &Tag::Byte(b))                   => a.cmp(&b),
+            (&Tag::Short(a), &Tag::Short(b))                 => a.cmp(&b),
+            (&Tag::Int(a), &Tag::Int(b))                     => a.cmp(&b),
+            (&Tag::Long(a), &Tag::Long(b))                   => a.cmp(&b),
+            (&Tag::Float(a), &Tag::Float(b))                 => a.total_cmp(&b),
+            (&Tag::Double(a), &Tag::Double(b))               => a.total_cmp(&b),
+            (Tag::String(a), Tag::String(b))                 => a.cmp(b),
+            (Tag::ByteArray(a), Tag::ByteArray(b))           => a.cmp(b),
+            (Tag::IntArray(a), Tag::IntArray(b))             => a.cmp(b),
+            (Tag::LongArray(a), Tag::LongArray(b))           => a.cmp(b),
+
+            (Tag::List(a), Tag::List(b)) =>
+                a.element_type.to_binary().cmp(&b.element_type.to_binary())
+                    .then_with(|| seq(a.elements.iter(), b.elements.iter())),
+
+            (Tag::Compound(a), Tag::Compound(b)) => {
+                let (a, b) = (entries(a), entries(b));
+
+                for (x, y) in a.iter().zip(b.iter()) {
+                    match x.0.cmp(y.0).then_with(|| x.1.total_cmp(y.1)) {
+                        Ordering::Equal => continue,
+                        o               => return o
+                    }
+                }
+
+                a.len().cmp(&b.len())
+            },
+
+            _ => self.get_type().to_binary().cmp(&other.get_type().to_binary())
+        }
+    }
+
+    /// Compare two tags for equality, allowing `Float` and `Double` values
+    /// to differ by up to `epsilon`. Everything else must match exactly.
+    pub fn approx_eq(&self, other: &Tag, epsilon: f64) -> bool {
+        match (self, other) {
+            (&Tag::Float(a), &Tag::Float(b))   => (a as f64 - b as f64).abs() <= epsilon,
+            (&Tag::Double(a), &Tag::Double(b)) => (a - b).abs() <= epsilon,
+
+            (Tag::List(a), Tag::List(b)) =>
+                a.element_type == b.element_type &&
+                a.elements.len() == b.elements.len() &&
+                a.elements.iter().zip(b.elements.iter()).all(|(x, y)| x.approx_eq(y, epsilon)),
+
+            (Tag::Compound(a), Tag::Compound(b)) =>
+                a.elements.len() == b.elements.len() &&
+                a.elements.iter().all(|(k, v)| match b.elements.get(k) {
+                    Some(w) => v.approx_eq(w, epsilon),
+                    None    => false
+                }),
+
+            _ => self == other
+        }
+    }
+
+    /// Return the number of bytes the tag's payload occupies when encoded,
+    /// not counting the type ID and name of a named tag.
+    pub fn encoded_len(&self) -> usize {
+        match *self {
+            Tag::End              => 0,
+            Tag::Byte(_)          => 1,
+            Tag::Short(_)         => 2,
+            Tag::Int(_)           => 4,
+            Tag::Long(_)          => 8,
+            Tag::Float(_)         => 4,
+            Tag::Double(_)        => 8,
+            Tag::String(ref x)    => 2 + mutf8::encoded_len(x),
+            Tag::ByteArray(ref x) => 4 + x.len(),
+            Tag::IntArray(ref x)  => 4 + 4 * x.len(),
+            Tag::LongArray(ref x) => 4 + 8 * x.len(),
+
+            Tag::List(ref x) =>
+                5 + x.elements.iter().map(|e| e.encoded_len()).sum::<usize>(),
+
+            Tag::Compound(ref x) => 1 + x.elements.iter()
+                .map(|(n, v)| 3 + mutf8::encoded_len(n) + v.encoded_len())
+                .sum::<usize>()
+        }
+    }
+
+    /// Return the number of bytes the tag occupies when written as a named
+    /// tag with `Encoder::write_tag`, including its type ID and name.
+    pub fn named_encoded_len(&self, name: &str) -> usize {
+        3 + mutf8::encoded_len(name) + self.encoded_len()
+    }
+
+    /// Check that every string and compound key in the tree can be encoded
+    /// as an NBT string, failing with the path of the first one that can't.
+    ///
+    /// Strings are always valid Unicode once decoded, so in practice this
+    /// catches strings whose modified UTF-8 encoding is too long for the
+    /// 16 bit length prefix.
+    pub fn validate_strings(&self) -> Result<()> {
+        self.validate_strings_at("")
+    }
+
+    fn validate_strings_at(&self, p: &str) -> Result<()> {
+        fn check(s: &str, p: &str) -> Result<()> {
+            if mutf8::encoded_len(s) > i16::MAX as usize {
+                Err(Error::InvalidString(p.to_owned()))
+            } else {
+                Ok(())
+            }
+        }
+
+        match *self {
+            Tag::String(ref x) => check(x, p),
+
+            Tag::List(ref x) => {
+                for (i, e) in x.elements.iter().enumerate() {
+                    e.validate_strings_at(&path::join_index(p, i))?;
+                }
+
+                Ok(())
+            },
+
+            Tag::Compound(ref x) => {
+                for (n, v) in x.elements.iter() {
+                    let child = path::join_key(p, n);
+
+                    check(n, &child)?;
+                    v.validate_strings_at(&child)?;
+                }
+
+                Ok(())
+            },
+
+            _ => Ok(())
+        }
+    }
+
+    /// Return the sorted paths of all lists in the tree with elements that
+    /// don't match the list's `element_type`.
+    ///
+    /// Such lists can't be encoded, but may be built by hand or by code that
+    /// modifies `elements` directly.
+    pub fn find_inconsistent_lists(&self) -> Vec<String> {
+        fn walk(t: &Tag, p: &str, out: &mut Vec<String>) {
+            match *t {
+                Tag::List(ref x) => {
+                    if x.elements.iter().any(|e| e.get_type() != x.element_type) {
+                        out.push(p.to_owned());
+                    }
+
+                    for (i, e) in x.elements.iter().enumerate() {
+                        walk(e, &path::join_index(p, i), out);
+                    }
+                },
+
+                Tag::Compound(ref x) => for (n, v) in x.elements.iter() {
+                    walk(v, &path::join_key(p, n), out);
+                },
+
+                _ => ()
+            }
+        }
+
+        let mut out = Vec::new();
+        walk(self, "", &mut out);
+        out.sort();
+
+        out
+    }
+
+    /// Break down the encoded size of the tag, written with the given name,
+    /// into a tree of per-subtree totals.
+    pub fn size_breakdown(&self, name: &str) -> SizeNode {
+        let mut root = self.size_node(String::new());
+        root.len += 3 + mutf8::encoded_len(name);
+
+        root
+    }
+
+    fn size_node(&self, p: String) -> SizeNode {
+        let children = match *self {
+            Tag::List(ref x) => x.elements.iter()
+                .enumerate()
+                .map(|(i, e)| e.size_node(path::join_index(&p, i)))
+                .collect(),
+
+            Tag::Compound(ref x) => x.elements.iter()
+                .map(|(n, v)| {
+                    let mut node = v.size_node(path::join_key(&p, n));
+                    node.len += 3 + mutf8::encoded_len(n);
+                    node
+                })
+                .collect(),
+
+            _ => Vec::new()
+        };
+
+        SizeNode {
+            path: p,
+            len: self.encoded_len(),
+            children
+        }
+    }
+}
+
+/// Encoded size of a subtree, as returned by `Tag::size_breakdown`.
+///
+/// The size of a compound entry includes its type ID and name, so the sizes
+/// of a container's children add up to its own size minus the container's
+/// fixed overhead (the list header or the compound end marker).
+#[derive(Debug, PartialEq)]
+pub struct SizeNode {
+    /// Path of the subtree relative to the root tag.
+    pub path: String,
+    /// Encoded size of the subtree in bytes.
+    pub len: usize,
+    /// Sizes of the subtree's immediate children.
+    pub children: Vec<SizeNode>
+}
+
+impl SizeNode {
+    /// Find the node for `path` among this node and its descendants.
+    pub fn find(&self, path: &str) -> Option<&SizeNode> {
+        if self.path == path {
+            return Some(self);
+        }
+
+        self.children.iter().filter_map(|c| c.find(path)).next()
+    }
+}
+
+#[test]
+fn test_size_breakdown() {
+    let mut level = CompoundData::new();
+    level.insert("Entities".to_owned(), Tag::List(ListData {
+        element_type: TagType::Long,
+        elements: (0 .. 100).map(Tag::Long).collect()
+    }));
+    level.insert("xPos".to_owned(), Tag::Int(3));
+
+    let mut root = CompoundData::new();
+    root.insert("Level".to_owned(), Tag::Compound(level));
+
+    let root = Tag::Compound(root);
+    let sizes = root.size_breakdown("");
+
+    assert_eq!(sizes.len, 3 + root.encoded_len());
+    assert_eq!(sizes.children.len(), 1);
+    assert_eq!(sizes.children[0].len + 1 + 3, sizes.len);
+
+    let level = sizes.find("Level").unwrap();
+    let ents = sizes.find("Level.Entities").unwrap();
+
+    assert_eq!(level.children.iter().map(|c| c.len).sum::<usize>() + 1,
+               root.encoded_len() - 1 - 3 - "Level".len());
+    assert_eq!(ents.len, 3 + 8 + 5 + 100 * 8);
+    assert!(ents.len * 10 > level.len * 9);
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[test]
+fn test_tag_index() {
+    use std::panic;
+
+    let mut tag = nbt!({ "Data": { "Player": { "Inventory": [{ "Count": 1_i8 }] } } });
+
+    assert_eq!(tag["Data"]["Player"]["Inventory"][0]["Count"], Tag::Byte(1));
+
+    tag["Data"]["Player"]["Inventory"][0]["Count"] = Tag::Byte(64);
+    assert_eq!(tag["Data"]["Player"]["Inventory"][0]["Count"], Tag::Byte(64));
+
+    assert_eq!(tag.index_opt("Missing"), None);
+    assert_eq!(tag["Data"].index_opt(0), None);
+
+    assert!(panic::catch_unwind(|| tag["Missing"].clone()).is_err());
+    assert!(panic::catch_unwind(|| tag["Data"][0].clone()).is_err());
+}
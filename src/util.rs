@@ -1,19 +1,28 @@
 //! Helper traits and types.
 
-use super::types::Tag;
-use super::traits::ToNbt;
+use super::types::{Tag, CompoundData, ListData, Compression};
+use super::traits::{ToNbt, FromNbt};
+use path::{self, Segment};
+
+use std::collections::HashSet;
+use std::hash::Hasher;
+use std::io::{self, Chain, Cursor, Read, Write};
+use std::mem;
+use std::rc::Rc;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
 
 /// Index trait for index operations where a result may not be available.
 pub trait IndexOpt<Idx> {
     type Output;
 
-    fn index_opt<'a>(&'a self, i: Idx) -> Option<&'a Self::Output>;
+    fn index_opt(&self, i: Idx) -> Option<&Self::Output>;
 }
 
 /// Index trait for mutable index operations where a result may not be
 /// available.
 pub trait IndexOptMut<Idx>: IndexOpt<Idx> {
-    fn index_opt_mut<'a>(&'a mut self, i: Idx) -> Option<&'a mut Self::Output>;
+    fn index_opt_mut(&mut self, i: Idx) -> Option<&mut Self::Output>;
 }
 
 
@@ -63,3 +72,595 @@ impl<'a> ToNbt for IntArrayWrapper<'a> {
         Tag::IntArray(v)
     }
 }
+
+
+/// Pool of unique strings, collected from decoders with
+/// `Decoder::set_interner`.
+///
+/// Tags don't refer to the pooled strings, so the pool is a record of the
+/// strings seen rather than a way to deduplicate them in memory.
+#[derive(Debug, Default)]
+pub struct StringInterner {
+    strings: HashSet<Rc<str>>
+}
+
+impl StringInterner {
+    /// Create an empty pool.
+    pub fn new() -> StringInterner {
+        StringInterner { strings: HashSet::new() }
+    }
+
+    /// Return the pooled copy of `s`, adding it to the pool if needed.
+    pub fn intern(&mut self, s: &str) -> Rc<str> {
+        if let Some(x) = self.strings.get(s) {
+            return x.clone();
+        }
+
+        let x: Rc<str> = Rc::from(s);
+        self.strings.insert(x.clone());
+        x
+    }
+
+    /// Return the pooled copy of `s`, if it has been interned.
+    pub fn get(&self, s: &str) -> Option<Rc<str>> {
+        self.strings.get(s).cloned()
+    }
+
+    /// Return the number of unique strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    /// Return whether the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+
+/// 64 bit FNV-1a hasher, used for `Tag::content_hash`.
+///
+/// Unlike `DefaultHasher` its output is stable across Rust versions and
+/// platforms, so hashes can be stored.
+#[derive(Debug, Clone, Copy)]
+pub struct ContentHasher {
+    state: u64
+}
+
+impl ContentHasher {
+    /// Create a hasher with the FNV offset basis as its state.
+    pub fn new() -> ContentHasher {
+        ContentHasher { state: 0xCBF2_9CE4_8422_2325 }
+    }
+}
+
+impl Default for ContentHasher {
+    fn default() -> ContentHasher {
+        ContentHasher::new()
+    }
+}
+
+impl Hasher for ContentHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.state ^= b as u64;
+            self.state = self.state.wrapping_mul(0x100_0000_01B3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Hasher::write(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// Return the name of the compression backend flate2 was built with.
+///
+/// The backend is chosen with the `miniz` (pure Rust, the default) and
+/// `zlib-ng` (C, faster for bulk work but needs a C toolchain) features.
+pub fn flate_backend() -> &'static str {
+    if cfg!(feature = "zlib-ng") {
+        "zlib-ng"
+    } else {
+        "miniz"
+    }
+}
+
+
+/// Guess the compression of `data` from its first two bytes.
+///
+/// Data starting with the GZip magic is `GZip`, data starting with a valid
+/// Zlib header is `Zlib`, and anything else, normally starting with the
+/// compound tag ID, is `Uncompressed`.
+pub fn sniff_compression(data: &[u8]) -> Compression {
+    if data.starts_with(&[0x1F, 0x8B]) {
+        Compression::GZip
+    } else if data.len() >= 2 && data[0] & 0x0F == 8 && (data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31) {
+        Compression::Zlib
+    } else {
+        Compression::Uncompressed
+    }
+}
+
+type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+enum AutoState<R> {
+    Sniffing(Vec<u8>, R),
+    Raw(Peeked<R>),
+    GZip(GzDecoder<Peeked<R>>),
+    Zlib(ZlibDecoder<Peeked<R>>),
+    Taken
+}
+
+/// Reader that decompresses GZip or Zlib data from `R`, or passes it through
+/// if it's uncompressed.
+///
+/// The compression is detected with `sniff_compression` on the first read,
+/// and the bytes inspected for it are still part of the data read.
+pub struct AutoDecompressReader<R> {
+    state: AutoState<R>
+}
+
+impl<R: Read> AutoDecompressReader<R> {
+    /// Wrap `reader`, detecting its compression on the first read.
+    pub fn new(reader: R) -> AutoDecompressReader<R> {
+        AutoDecompressReader { state: AutoState::Sniffing(Vec::with_capacity(2), reader) }
+    }
+
+    /// Return the detected compression, or `None` before the first read.
+    pub fn compression(&self) -> Option<Compression> {
+        match self.state {
+            AutoState::Raw(_)  => Some(Compression::Uncompressed),
+            AutoState::GZip(_) => Some(Compression::GZip),
+            AutoState::Zlib(_) => Some(Compression::Zlib),
+            _                  => None
+        }
+    }
+
+    fn sniff(&mut self) -> io::Result<()> {
+        if let AutoState::Sniffing(ref mut head, ref mut source) = self.state {
+            let mut buf = [0_u8; 2];
+
+            while head.len() < 2 {
+                match source.read(&mut buf[.. 2 - head.len()]) {
+                    Ok(0)  => break,
+                    Ok(n)  => head.extend_from_slice(&buf[.. n]),
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(e) => return Err(e)
+                }
+            }
+        } else {
+            return Ok(());
+        }
+
+        if let AutoState::Sniffing(head, source) = mem::replace(&mut self.state, AutoState::Taken) {
+            let c = sniff_compression(&head);
+            let peeked = Cursor::new(head).chain(source);
+
+            self.state = match c {
+                Compression::GZip => AutoState::GZip(GzDecoder::new(peeked)),
+                Compression::Zlib => AutoState::Zlib(ZlibDecoder::new(peeked)),
+                _                 => AutoState::Raw(peeked)
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for AutoDecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.sniff()?;
+
+        match self.state {
+            AutoState::Raw(ref mut r)  => r.read(buf),
+            AutoState::GZip(ref mut r) => r.read(buf),
+            AutoState::Zlib(ref mut r) => r.read(buf),
+            _                          => unreachable!()
+        }
+    }
+}
+
+
+/// Find the section with the given `Y` index in a chunk.
+///
+/// Both the current layout, with a `sections` list at the root of the chunk,
+/// and the legacy `Level.Sections` layout are supported.
+pub fn section_by_y(chunk: &Tag, y: i8) -> Option<&CompoundData> {
+    let modern = [Segment::Key("sections".to_owned())];
+    let legacy = [Segment::Key("Level".to_owned()), Segment::Key("Sections".to_owned())];
+
+    let sections = match path::get(chunk, &modern).or_else(|| path::get(chunk, &legacy)) {
+        Some(Tag::List(l)) => l,
+        _                  => return None
+    };
+
+    sections.elements.iter()
+        .filter_map(|s| match *s {
+            Tag::Compound(ref c) => Some(c),
+            _                    => None
+        })
+        .find(|c| c.elements.get("Y") == Some(&Tag::Byte(y)))
+}
+
+// A temporary directory for a test, unique to this process.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> ::std::path::PathBuf {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    let dir = env::temp_dir().join(format!("nbt-test-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+#[test]
+fn test_auto_decompress_reader() {
+    use std::io::Write;
+    use flate2;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use decode::Decoder;
+
+    // {"": {n: 5}}
+    let raw = vec![10, 0, 0, 3, 0, 1, b'n', 0, 0, 0, 5, 0];
+
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(&raw).unwrap();
+    let gz = gz.finish().unwrap();
+
+    let mut z = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    z.write_all(&raw).unwrap();
+    let z = z.finish().unwrap();
+
+    let cases = vec![
+        (raw.clone(), Compression::Uncompressed),
+        (gz, Compression::GZip),
+        (z, Compression::Zlib)
+    ];
+
+    for (data, c) in cases {
+        let mut r = AutoDecompressReader::new(Cursor::new(data.clone()));
+        assert_eq!(r.compression(), None);
+
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+
+        assert_eq!(r.compression(), Some(c));
+        assert_eq!(out, raw);
+
+        let mut r = AutoDecompressReader::new(Cursor::new(data));
+        let (_, tag) = Decoder::from_reader(&mut r).read_tag().unwrap();
+        assert_eq!(tag.get_path("n"), Some(&Tag::Int(5)));
+    }
+}
+
+#[test]
+fn test_section_by_y() {
+    use super::types::TagType;
+
+    let section = |y: i8| {
+        let mut c = CompoundData::new();
+        c.insert("Y".to_owned(), Tag::Byte(y));
+        c.insert("SkyLight".to_owned(), Tag::ByteArray(vec![y as u8]));
+        Tag::Compound(c)
+    };
+
+    let sections = || Tag::List(ListData {
+        element_type: TagType::Compound,
+        elements: vec![section(-1), section(0), section(3)]
+    });
+
+    let mut modern = CompoundData::new();
+    modern.insert("sections".to_owned(), sections());
+    let modern = Tag::Compound(modern);
+
+    let mut level = CompoundData::new();
+    level.insert("Sections".to_owned(), sections());
+    let mut legacy = CompoundData::new();
+    legacy.insert("Level".to_owned(), Tag::Compound(level));
+    let legacy = Tag::Compound(legacy);
+
+    for chunk in &[modern, legacy] {
+        let s = section_by_y(chunk, 3).unwrap();
+
+        assert_eq!(s.elements.get("SkyLight"), Some(&Tag::ByteArray(vec![3])));
+        assert!(section_by_y(chunk, -1).is_some());
+        assert!(section_by_y(chunk, 5).is_none());
+    }
+}
+
+
+/// How block state indices are packed into the longs of a section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packing {
+    /// Indices don't span across longs, leaving unused high bits (1.16 and
+    /// later).
+    Aligned,
+    /// Indices are packed back to back and may span two longs (1.13 to 1.15).
+    Spanning
+}
+
+const SECTION_BLOCKS: usize = 4096;
+
+/// Return the number of bits per block used to store indices into a palette
+/// of `palette_len` entries.
+pub fn bits_per_block(palette_len: usize) -> usize {
+    let bits = if palette_len > 1 {
+        64 - ((palette_len - 1) as u64).leading_zeros() as usize
+    } else {
+        0
+    };
+
+    bits.max(4)
+}
+
+fn packed_len(bits: usize, packing: Packing) -> usize {
+    match packing {
+        Packing::Aligned  => SECTION_BLOCKS.div_ceil(64 / bits),
+        Packing::Spanning => (SECTION_BLOCKS * bits).div_ceil(64)
+    }
+}
+
+/// Unpack the block states of a chunk section into one palette index per
+/// block, in YZX order.
+///
+/// Both the `block_states` compound of current versions and the legacy
+/// `Palette`/`BlockStates` pair are supported. A single entry palette without
+/// data yields all zeros. Returns `None` if the palette is missing or the
+/// data is too short for it.
+pub fn decode_block_states(section: &CompoundData, packing: Packing) -> Option<Vec<usize>> {
+    let (palette, data) = match section.elements.get("block_states") {
+        Some(Tag::Compound(c)) => (c.elements.get("palette"), c.elements.get("data")),
+        _ => (section.elements.get("Palette"), section.elements.get("BlockStates"))
+    };
+
+    let palette_len = match palette {
+        Some(&Tag::List(ListData { ref elements, .. })) if !elements.is_empty() => elements.len(),
+        _ => return None
+    };
+
+    let data = match data {
+        Some(Tag::LongArray(x))  => x,
+        None if palette_len == 1 => return Some(vec![0; SECTION_BLOCKS]),
+        _                        => return None
+    };
+
+    let bits = bits_per_block(palette_len);
+
+    if data.len() < packed_len(bits, packing) {
+        return None;
+    }
+
+    let mask = (1_u64 << bits) - 1;
+
+    Some((0 .. SECTION_BLOCKS).map(|i| {
+        let v = match packing {
+            Packing::Aligned => {
+                let per_long = 64 / bits;
+                (data[i / per_long] as u64) >> ((i % per_long) * bits)
+            },
+
+            Packing::Spanning => {
+                let bit = i * bits;
+                let (word, shift) = (bit / 64, bit % 64);
+                let mut v = (data[word] as u64) >> shift;
+
+                if shift + bits > 64 {
+                    v |= (data[word + 1] as u64) << (64 - shift);
+                }
+
+                v
+            }
+        };
+
+        (v & mask) as usize
+    }).collect())
+}
+
+/// Pack one palette index per block into longs, the inverse of
+/// `decode_block_states`.
+///
+/// Returns `None` unless `indices` holds 4096 entries, each less than
+/// `palette_len`.
+pub fn encode_block_states(indices: &[usize], palette_len: usize, packing: Packing) -> Option<Vec<i64>> {
+    if indices.len() != SECTION_BLOCKS || indices.iter().any(|&v| v >= palette_len) {
+        return None;
+    }
+
+    let bits = bits_per_block(palette_len);
+    let mut data = vec![0_u64; packed_len(bits, packing)];
+
+    for (i, &v) in indices.iter().enumerate() {
+        let v = v as u64;
+
+        match packing {
+            Packing::Aligned => {
+                let per_long = 64 / bits;
+                data[i / per_long] |= v << ((i % per_long) * bits);
+            },
+
+            Packing::Spanning => {
+                let bit = i * bits;
+                let (word, shift) = (bit / 64, bit % 64);
+                data[word] |= v << shift;
+
+                if shift + bits > 64 {
+                    data[word + 1] |= v >> (64 - shift);
+                }
+            }
+        }
+    }
+
+    Some(data.into_iter().map(|x| x as i64).collect())
+}
+
+#[test]
+fn test_block_states() {
+    use super::types::TagType;
+
+    assert_eq!(bits_per_block(1), 4);
+    assert_eq!(bits_per_block(16), 4);
+    assert_eq!(bits_per_block(17), 5);
+    assert_eq!(bits_per_block(33), 6);
+
+    // 5 bits per block: aligned packing wastes 4 bits per long, spanning
+    // packing doesn't
+    let palette_len = 20;
+    let indices: Vec<usize> = (0 .. SECTION_BLOCKS).map(|i| (i * 7) % palette_len).collect();
+
+    let palette = || Tag::List(ListData {
+        element_type: TagType::Compound,
+        elements: (0 .. palette_len).map(|_| Tag::Compound(CompoundData::new())).collect()
+    });
+
+    for &packing in &[Packing::Aligned, Packing::Spanning] {
+        let data = encode_block_states(&indices, palette_len, packing).unwrap();
+
+        assert_eq!(data.len(), match packing {
+            Packing::Aligned  => 342,
+            Packing::Spanning => 320
+        });
+
+        let mut states = CompoundData::new();
+        states.insert("data".to_owned(), Tag::LongArray(data.clone()));
+        states.insert("palette".to_owned(), palette());
+
+        let mut modern = CompoundData::new();
+        modern.insert("block_states".to_owned(), Tag::Compound(states));
+
+        let mut legacy = CompoundData::new();
+        legacy.insert("BlockStates".to_owned(), Tag::LongArray(data));
+        legacy.insert("Palette".to_owned(), palette());
+
+        assert_eq!(decode_block_states(&modern, packing), Some(indices.clone()));
+        assert_eq!(decode_block_states(&legacy, packing), Some(indices.clone()));
+    }
+
+    let mut single = CompoundData::new();
+    single.insert("Palette".to_owned(), Tag::List(ListData {
+        element_type: TagType::Compound,
+        elements: vec![Tag::Compound(CompoundData::new())]
+    }));
+
+    assert_eq!(decode_block_states(&single, Packing::Aligned), Some(vec![0; SECTION_BLOCKS]));
+    assert_eq!(decode_block_states(&CompoundData::new(), Packing::Aligned), None);
+
+    // Wrong lengths and indices past the palette are rejected
+    let mut long = indices.clone();
+    long.push(0);
+
+    assert_eq!(encode_block_states(&long, palette_len, Packing::Aligned), None);
+    assert_eq!(encode_block_states(&indices[1 ..], palette_len, Packing::Aligned), None);
+
+    let mut out_of_range = indices.clone();
+    out_of_range[100] = palette_len;
+
+    assert_eq!(encode_block_states(&out_of_range, palette_len, Packing::Spanning), None);
+}
+
+
+/// An item stack from an inventory list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    /// Inventory slot the stack is in.
+    pub slot: i8,
+    /// Namespaced item ID, like `minecraft:stone`.
+    pub id: String,
+    /// Number of items in the stack.
+    pub count: i8,
+    /// Extra item data, if any.
+    pub tag: Option<CompoundData>
+}
+
+impl ItemStack {
+    /// Convert an item compound with `Slot`, `id`, `Count` and an optional
+    /// `tag` compound.
+    pub fn from_compound(item: &CompoundData) -> Option<ItemStack> {
+        let get = |k: &str| item.elements.get(k);
+
+        Some(ItemStack {
+            slot: get("Slot").and_then(i8::from_nbt)?,
+            id: get("id").and_then(String::from_nbt)?,
+            count: get("Count").and_then(i8::from_nbt)?,
+            tag: match get("tag") {
+                Some(Tag::Compound(c)) => Some(c.clone()),
+                None                   => None,
+                Some(_)                => return None
+            }
+        })
+    }
+}
+
+impl FromNbt for ItemStack {
+    fn from_nbt(val: &Tag) -> Option<ItemStack> {
+        match *val {
+            Tag::Compound(ref c) => ItemStack::from_compound(c),
+            _                    => None
+        }
+    }
+}
+
+/// Read the `Inventory` list of a player compound as item stacks.
+///
+/// Returns `None` if there is no inventory list, or if any of its entries
+/// isn't a valid item compound.
+pub fn read_inventory(player: &CompoundData) -> Option<Vec<ItemStack>> {
+    match player.elements.get("Inventory") {
+        Some(x @ &Tag::List(_)) => Vec::from_nbt(x),
+        _                       => None
+    }
+}
+
+#[test]
+fn test_read_inventory() {
+    use super::types::TagType;
+
+    let item = |slot: i8, id: &str, count: i8, tag: Option<CompoundData>| {
+        let mut c = CompoundData::new();
+        c.insert("Slot".to_owned(), Tag::Byte(slot));
+        c.insert("id".to_owned(), Tag::String(id.to_owned()));
+        c.insert("Count".to_owned(), Tag::Byte(count));
+
+        if let Some(t) = tag {
+            c.insert("tag".to_owned(), Tag::Compound(t));
+        }
+
+        Tag::Compound(c)
+    };
+
+    let mut ench = CompoundData::new();
+    ench.insert("Damage".to_owned(), Tag::Int(12));
+
+    let mut player = CompoundData::new();
+    player.insert("Inventory".to_owned(), Tag::List(ListData {
+        element_type: TagType::Compound,
+        elements: vec![
+            item(0, "minecraft:stone", 64, None),
+            item(9, "minecraft:iron_pickaxe", 1, Some(ench.clone()))
+        ]
+    }));
+
+    let inv = read_inventory(&player).unwrap();
+
+    assert_eq!(inv.len(), 2);
+    assert_eq!(inv[0], ItemStack { slot: 0, id: "minecraft:stone".to_owned(), count: 64, tag: None });
+    assert_eq!(inv[1].slot, 9);
+    assert_eq!(inv[1].id, "minecraft:iron_pickaxe");
+    assert_eq!(inv[1].tag, Some(ench));
+
+    assert_eq!(read_inventory(&CompoundData::new()), None);
+}
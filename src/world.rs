@@ -0,0 +1,221 @@
+//! Read chunks from the region files of a Minecraft world.
+
+use super::{Error, Result, Tag};
+use region::RegionFile;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::vec;
+
+// Parse the region coordinates out of a file name like `r.-1.2.mca`.
+fn region_coords(name: &str) -> Option<(i32, i32)> {
+    let mut parts = name.split('.');
+
+    match (parts.next(), parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("r"), Some(x), Some(z), Some("mca"), None) =>
+            Some((x.parse().ok()?, z.parse().ok()?)),
+        _ => None
+    }
+}
+
+/// Iterator over the chunks of a region directory, created by `iter_chunks`.
+pub struct Chunks {
+    files: vec::IntoIter<(i32, i32, PathBuf)>,
+    current: Option<(i32, i32, RegionFile<File>)>,
+    slot: usize,
+    err: Option<Error>
+}
+
+impl Iterator for Chunks {
+    type Item = Result<(i32, i32, Tag)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.err.take() {
+            return Some(Err(e));
+        }
+
+        loop {
+            if let Some((rx, rz, ref mut region)) = self.current {
+                while self.slot < 1024 {
+                    let (lx, lz) = ((self.slot % 32) as u8, (self.slot / 32) as u8);
+                    self.slot += 1;
+
+                    let x = rx * 32 + lx as i32;
+                    let z = rz * 32 + lz as i32;
+
+                    match region.chunk(lx, lz) {
+                        Ok(Some(tag)) => return Some(Ok((x, z, tag))),
+                        Ok(None)      => continue,
+                        Err(e)        => return Some(Err(e))
+                    }
+                }
+            }
+
+            let (rx, rz, file) = self.files.next()?;
+
+            self.current = None;
+            self.slot = 0;
+
+            let region = match File::open(file) {
+                Ok(f)  => RegionFile::new(f),
+                Err(e) => Err(Error::from(e))
+            };
+
+            match region {
+                Ok(r)  => self.current = Some((rx, rz, r)),
+                Err(e) => return Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterate over every chunk stored in the `r.X.Z.mca` region files in
+/// `region_dir`, yielding each chunk's global chunk coordinates and NBT.
+///
+/// Other files in the directory are ignored. Errors, including failure to
+/// list the directory, are yielded in place of the affected chunk or file.
+pub fn iter_chunks(region_dir: &str) -> Chunks {
+    let mut files = Vec::new();
+    let mut err = None;
+
+    match fs::read_dir(region_dir) {
+        Ok(entries) => for entry in entries {
+            match entry {
+                Ok(entry) => {
+                    let coords = entry.file_name().to_str().and_then(region_coords);
+
+                    if let Some((x, z)) = coords {
+                        files.push((x, z, entry.path()));
+                    }
+                },
+
+                Err(e) => { err = Some(Error::from(e)); break; }
+            }
+        },
+
+        Err(e) => err = Some(Error::from(e))
+    }
+
+    files.sort();
+
+    Chunks {
+        files: files.into_iter(),
+        current: None,
+        slot: 0,
+        err
+    }
+}
+
+/// Iterator over the entities of a world, created by `iter_entities`.
+pub struct Entities {
+    chunks: Chunks
+}
+
+impl Iterator for Entities {
+    type Item = Result<(i32, i32, Vec<Tag>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (x, z, tag) = match self.chunks.next()? {
+            Ok(c)  => c,
+            Err(e) => return Some(Err(e))
+        };
+
+        let entities = match tag {
+            Tag::Compound(mut c) => match c.elements.remove("Entities") {
+                Some(Tag::List(l)) => Ok(l.elements),
+                None               => Ok(Vec::new()),
+                Some(_)            => Err(Error::Invalid)
+            },
+            _ => Err(Error::Invalid)
+        };
+
+        Some(entities.map(|e| (x, z, e)))
+    }
+}
+
+/// Iterate over the entities stored in the `entities` directory of the world
+/// in `world_dir`, yielding each chunk's global chunk coordinates and the
+/// compounds in its `Entities` list.
+///
+/// Entity region files have the same format as block region files, so this
+/// reads them like `iter_chunks` does. Chunks without an `Entities` list
+/// yield no entities, and other root tags fail with `Error::Invalid`.
+pub fn iter_entities(world_dir: &str) -> Entities {
+    let dir = Path::new(world_dir).join("entities");
+
+    Entities {
+        chunks: iter_chunks(&dir.to_string_lossy())
+    }
+}
+
+// Build a region file holding a single zlib compressed chunk at local
+// `(x, z)`, with the encoded named tag `raw`.
+#[cfg(test)]
+fn region_with_chunk(x: u8, z: u8, raw: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    use flate2;
+    use flate2::write::ZlibEncoder;
+    use region::build_region;
+
+    let mut enc = ZlibEncoder::new(vec![2], flate2::Compression::default());
+    enc.write_all(raw).unwrap();
+
+    build_region(&[(x, z, enc.finish().unwrap())])
+}
+
+#[test]
+fn test_iter_chunks() {
+    use util;
+
+    // {"": {xPos: 34}}
+    let raw = [10, 0, 0, 3, 0, 4, b'x', b'P', b'o', b's', 0, 0, 0, 34, 0];
+
+    let dir = util::test_dir("iter-chunks");
+    fs::write(dir.join("r.1.-1.mca"), region_with_chunk(2, 3, &raw)).unwrap();
+    fs::write(dir.join("notes.txt"), b"not a region").unwrap();
+
+    let chunks: Vec<_> = iter_chunks(dir.to_str().unwrap())
+        .map(|c| c.unwrap())
+        .collect();
+
+    assert_eq!(chunks.len(), 1);
+    assert_eq!((chunks[0].0, chunks[0].1), (34, -29));
+
+    match chunks[0].2 {
+        Tag::Compound(ref c) => assert_eq!(c.elements.get("xPos"), Some(&Tag::Int(34))),
+        _                    => panic!("expected a compound")
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_iter_entities() {
+    use encode;
+    use util;
+    use super::{CompoundData, ListData, TagType};
+
+    let mut zombie = CompoundData::new();
+    zombie.insert("id".to_owned(), Tag::String("minecraft:zombie".to_owned()));
+
+    let mut list = ListData::new(TagType::Compound);
+    list.elements.push(Tag::Compound(zombie.clone()));
+
+    let mut chunk = CompoundData::new();
+    chunk.insert("Position".to_owned(), Tag::IntArray(vec![-32, 0]));
+    chunk.insert("Entities".to_owned(), Tag::List(list));
+
+    let raw = encode::to_bytes("", &Tag::Compound(chunk)).unwrap();
+
+    let dir = util::test_dir("iter-entities");
+    fs::create_dir_all(dir.join("entities")).unwrap();
+    fs::write(dir.join("entities").join("r.-1.0.mca"), region_with_chunk(0, 0, &raw)).unwrap();
+
+    let chunks: Vec<_> = iter_entities(dir.to_str().unwrap())
+        .map(|c| c.unwrap())
+        .collect();
+
+    assert_eq!(chunks, [(-32, 0, vec![Tag::Compound(zombie)])]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
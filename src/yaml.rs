@@ -0,0 +1,170 @@
+//! Render tags as YAML-like indented text for reading.
+
+use super::{Tag, TagType};
+
+fn type_name(t: TagType) -> &'static str {
+    match t {
+        TagType::End       => "end",
+        TagType::Byte      => "byte",
+        TagType::Short     => "short",
+        TagType::Int       => "int",
+        TagType::Long      => "long",
+        TagType::Float     => "float",
+        TagType::Double    => "double",
+        TagType::String    => "string",
+        TagType::ByteArray => "byte array",
+        TagType::IntArray  => "int array",
+        TagType::LongArray => "long array",
+        TagType::List      => "list",
+        TagType::Compound  => "compound"
+    }
+}
+
+// Type annotation for a value, including the element type of lists.
+fn annotation(tag: &Tag) -> String {
+    match *tag {
+        Tag::List(ref l) => format!("list of {}", type_name(l.element_type)),
+        _                => type_name(tag.get_type()).to_owned()
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"'  => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c    => out.push(c)
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+fn key(k: &str) -> String {
+    if !k.is_empty() && k.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        k.to_owned()
+    } else {
+        quote(k)
+    }
+}
+
+fn list<T: ToString>(values: &[T]) -> String {
+    let items: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", items.join(", "))
+}
+
+// Single line rendering of values that don't get a block of their own.
+fn inline(tag: &Tag) -> String {
+    match *tag {
+        Tag::End              => "null".to_owned(),
+        Tag::Byte(x)          => x.to_string(),
+        Tag::Short(x)         => x.to_string(),
+        Tag::Int(x)           => x.to_string(),
+        Tag::Long(x)          => x.to_string(),
+        Tag::Float(x)         => format!("{:?}", x),
+        Tag::Double(x)        => format!("{:?}", x),
+        Tag::String(ref x)    => quote(x),
+        Tag::ByteArray(ref x) => list(x),
+        Tag::IntArray(ref x)  => list(x),
+        Tag::LongArray(ref x) => list(x),
+        Tag::List(_)          => "[]".to_owned(),
+        Tag::Compound(_)      => "{}".to_owned()
+    }
+}
+
+// Whether `tag` is rendered as an indented block below its key.
+fn is_block(tag: &Tag) -> bool {
+    match *tag {
+        Tag::List(ref l)     => !l.elements.is_empty(),
+        Tag::Compound(ref c) => !c.elements.is_empty(),
+        _                    => false
+    }
+}
+
+// Write the children of a compound or list, indented by `depth` levels.
+fn write_children(out: &mut String, tag: &Tag, depth: usize) {
+    match *tag {
+        Tag::Compound(ref c) => {
+            let mut keys: Vec<&String> = c.elements.keys().collect();
+            keys.sort();
+
+            for k in keys {
+                write_entry(out, &format!("{}:", key(k)), &c.elements[k], depth);
+            }
+        },
+
+        Tag::List(ref l) => for v in l.elements.iter() {
+            write_entry(out, "-", v, depth);
+        },
+
+        _ => ()
+    }
+}
+
+// Write one compound entry or list item, introduced by `prefix`.
+fn write_entry(out: &mut String, prefix: &str, tag: &Tag, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    if is_block(tag) {
+        out.push_str(&format!("{}{}  # {}\n", indent, prefix, annotation(tag)));
+        write_children(out, tag, depth + 1);
+    } else {
+        out.push_str(&format!("{}{} {}  # {}\n", indent, prefix, inline(tag), annotation(tag)));
+    }
+}
+
+impl Tag {
+    /// Render the tag as indented, YAML-like text for people to read.
+    ///
+    /// Compounds become `key: value` blocks with sorted keys, lists become
+    /// `- item` sequences and arrays are written inline. Each value is
+    /// followed by a comment naming its NBT type. This is a view only; use
+    /// SNBT for text that needs to be read back.
+    pub fn to_yaml(&self) -> String {
+        let mut out = String::new();
+
+        if is_block(self) {
+            write_children(&mut out, self, 0);
+        } else {
+            out.push_str(&format!("{}  # {}\n", inline(self), annotation(self)));
+        }
+
+        out
+    }
+}
+
+#[test]
+fn test_to_yaml() {
+    use snbt::parse_snbt;
+
+    let tag = parse_snbt("{Name:\"Steve \\\"the\\\" miner\", Health:20.0f, Pos:[1.5d, 64.0d], \
+                          Inventory:[{Slot:0b, id:\"stone\"}], Empty:{}, Bits:[I;1,2], \
+                          \"odd key\":[[1s], []]}").unwrap();
+
+    assert_eq!(tag.to_yaml(), "\
+Bits: [1, 2]  # int array
+Empty: {}  # compound
+Health: 20.0  # float
+Inventory:  # list of compound
+  -  # compound
+    Slot: 0  # byte
+    id: \"stone\"  # string
+Name: \"Steve \\\"the\\\" miner\"  # string
+Pos:  # list of double
+  - 1.5  # double
+  - 64.0  # double
+\"odd key\":  # list of list
+  -  # list of short
+    - 1  # short
+  - []  # list of end
+");
+
+    assert_eq!(Tag::Int(3).to_yaml(), "3  # int\n");
+}
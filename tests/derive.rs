@@ -0,0 +1,115 @@
+#![cfg(feature = "derive")]
+
+extern crate nbt;
+
+use nbt::{Tag, CompoundData, ToNbt, FromNbt};
+use nbt::traits::{ToNbt, FromNbt};
+
+#[derive(Debug, PartialEq, ToNbt, FromNbt)]
+struct Player {
+    #[nbt(rename = "Health")]
+    health: f32,
+    #[nbt(rename = "XpLevel")]
+    xp_level: i32,
+    #[nbt(rename = "CustomName")]
+    custom_name: Option<String>,
+    #[nbt(rename = "Pos")]
+    pos: Vec<f64>,
+    #[nbt(skip)]
+    dirty: bool
+}
+
+fn player() -> Player {
+    Player {
+        health: 20.0,
+        xp_level: 7,
+        custom_name: None,
+        pos: vec![0.5, 64.0, -3.5],
+        dirty: true
+    }
+}
+
+#[test]
+fn test_derive_to_nbt() {
+    let tag = player().to_nbt();
+    let c = match tag {
+        Tag::Compound(ref c) => c,
+        _                    => panic!("expected a compound")
+    };
+
+    assert_eq!(c.elements.len(), 3);
+    assert_eq!(c.elements.get("Health"), Some(&Tag::Float(20.0)));
+    assert_eq!(c.elements.get("XpLevel"), Some(&Tag::Int(7)));
+    assert!(c.elements.contains_key("Pos"));
+    assert!(!c.elements.contains_key("CustomName"));
+    assert!(!c.elements.contains_key("dirty"));
+}
+
+#[test]
+fn test_derive_from_nbt() {
+    let mut p = player();
+    p.custom_name = Some("Steve".to_owned());
+
+    let mut back = Player::from_nbt(&p.to_nbt()).unwrap();
+    assert_eq!(back.custom_name, Some("Steve".to_owned()));
+    assert_eq!(back.pos, p.pos);
+    assert!(!back.dirty);
+
+    // Skipped fields aren't compared
+    assert!(back.nbt_eq(&p));
+    back.pos[1] = 65.0;
+    assert!(!back.nbt_eq(&p));
+
+    // Missing optional fields are fine, missing required ones aren't
+    let mut c = CompoundData::new();
+    c.insert("Health".to_owned(), Tag::Float(1.0));
+    c.insert("XpLevel".to_owned(), Tag::Int(0));
+    c.insert("Pos".to_owned(), vec![0.0_f64, 0.0, 0.0][..].to_nbt());
+
+    assert_eq!(Player::from_nbt(&Tag::Compound(c.clone())).map(|p| p.custom_name), Some(None));
+
+    c.remove("XpLevel");
+    assert_eq!(Player::from_nbt(&Tag::Compound(c)), None);
+    assert_eq!(Player::from_nbt(&Tag::Int(1)), None);
+}
+
+// Has no `PartialEq`, so `nbt_eq` compares it by its NBT
+#[derive(Debug, ToNbt, FromNbt)]
+struct Display {
+    #[nbt(rename = "Name")]
+    name: String
+}
+
+#[derive(Debug, ToNbt, FromNbt)]
+struct Item {
+    id: String,
+    display: Display,
+    #[nbt(rename = "Count")]
+    count: i8,
+    #[nbt(ignore_eq, rename = "LastTouched")]
+    last_touched: i64
+}
+
+#[test]
+fn test_derive_ignore_eq() {
+    let item = |name: &str, n, t| Item {
+        id: "minecraft:stone".to_owned(),
+        display: Display { name: name.to_owned() },
+        count: n,
+        last_touched: t
+    };
+
+    let a = item("Rock", 3, 100);
+    let b = item("Rock", 3, 250);
+    let c = item("Rock", 4, 100);
+    let d = item("Pebble", 3, 100);
+
+    assert!(a.nbt_eq(&b));
+    assert!(!a.nbt_eq(&c));
+    assert!(!a.nbt_eq(&d));
+
+    // Ignored fields are still written and read
+    let back = Item::from_nbt(&b.to_nbt()).unwrap();
+    assert_eq!(back.last_touched, 250);
+    assert!(a.to_nbt() != b.to_nbt());
+}